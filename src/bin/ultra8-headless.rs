@@ -0,0 +1,205 @@
+// Runs a rom without a window and prints the final machine state
+//
+// usage: ultra8-headless <rom> [--cycles N] [--ipf N] [--gdb PORT] [--trace FILE [--trace-binary]
+//                                [--trace-range START-END] [--trace-class CLASS,...]] [--profile N]
+//                                [--coverage FILE] [--screenshot FILE] [--gif FILE [--gif-frames START-END]]
+//                                [--scale N] [--palette NAME]
+//
+// The platform is detected from the rom and sets the quirks and how many instructions run per frame,
+// timers tick every --ipf instructions instead when it is given
+//
+// --profile prints the N hottest addresses and subroutines after the run, --coverage writes the code/data
+// coverage map, one flag byte per address or a range summary if FILE ends in .txt
+//
+// --screenshot saves the final framebuffer as a PNG, scaled up --scale times. --gif records every frame, or
+// the frames numbered START to END inclusive, to an animated GIF at the same scale
+//
+// With --gdb the rom is run by a gdb client connected to localhost:PORT instead of for N cycles
+
+use std::env;
+use std::fs;
+use std::ops::RangeInclusive;
+
+use anyhow::{bail, Context, Result};
+use ultra8::trace::{InstructionClass, TraceFilter};
+use ultra8::capture::{GifRecorder, Image};
+use ultra8::{Cpu, Palette, Platform, Profiler, TraceFormat, Tracer};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut rom = None;
+    let mut cycles: u64 = 1000;
+    let mut ipf: Option<u64> = None;
+    let mut gdb_port: Option<u16> = None;
+    let mut trace_file: Option<String> = None;
+    let mut trace_format = TraceFormat::Text;
+    let mut trace_filter = TraceFilter::default();
+    let mut profile: Option<usize> = None;
+    let mut coverage_file: Option<String> = None;
+    let mut screenshot: Option<String> = None;
+    let mut gif_file: Option<String> = None;
+    let mut gif_frames: RangeInclusive<u64> = 0..=u64::MAX;
+    let mut scale: usize = 1;
+    let mut palette = Palette::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--cycles" => {
+                let value = iter.next().context("--cycles needs a value")?;
+                cycles = value.parse().context("--cycles must be a number")?;
+            }
+            "--ipf" => {
+                let value = iter.next().context("--ipf needs a value")?;
+                let value: u64 = value.parse().context("--ipf must be a number")?;
+                if value == 0 {
+                    bail!("--ipf must be at least 1");
+                }
+                ipf = Some(value);
+            }
+            "--gdb" => {
+                let value = iter.next().context("--gdb needs a port")?;
+                gdb_port = Some(value.parse().context("--gdb must be a port number")?);
+            }
+            "--trace" => trace_file = Some(iter.next().context("--trace needs a file")?.clone()),
+            "--trace-binary" => trace_format = TraceFormat::Binary,
+            "--trace-range" => {
+                let value = iter.next().context("--trace-range needs START-END")?;
+                let (start, end) = value.split_once('-').context("--trace-range needs START-END")?;
+                let start = u16::from_str_radix(start.trim_start_matches("0x"), 16).context("Bad range start")?;
+                let end = u16::from_str_radix(end.trim_start_matches("0x"), 16).context("Bad range end")?;
+                trace_filter.range = Some(start..=end);
+            }
+            "--trace-class" => {
+                let value = iter.next().context("--trace-class needs a list of classes")?;
+                let classes = value
+                    .split(',')
+                    .map(|name| InstructionClass::from_name(name).with_context(|| format!("Unknown instruction class {}", name)))
+                    .collect::<Result<Vec<_>>>()?;
+                trace_filter.classes = Some(classes);
+            }
+            "--profile" => {
+                let value = iter.next().context("--profile needs a number of entries")?;
+                profile = Some(value.parse().context("--profile must be a number")?);
+            }
+            "--coverage" => coverage_file = Some(iter.next().context("--coverage needs a file")?.clone()),
+            "--screenshot" => screenshot = Some(iter.next().context("--screenshot needs a file")?.clone()),
+            "--gif" => gif_file = Some(iter.next().context("--gif needs a file")?.clone()),
+            "--gif-frames" => {
+                let value = iter.next().context("--gif-frames needs START-END")?;
+                let (start, end) = value.split_once('-').context("--gif-frames needs START-END")?;
+                let start = start.parse().context("Bad first frame")?;
+                let end = end.parse().context("Bad last frame")?;
+                gif_frames = start..=end;
+            }
+            "--scale" => {
+                let value = iter.next().context("--scale needs a value")?;
+                scale = value.parse().context("--scale must be a number")?;
+            }
+            "--palette" => {
+                let value = iter.next().context("--palette needs a name")?;
+                palette = Palette::preset(value).with_context(|| format!("Unknown palette {}", value))?;
+            }
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => bail!("Unexpected argument {}", arg),
+        }
+    }
+
+    let Some(rom) = rom else {
+        bail!("usage: ultra8-headless <rom> [--cycles N] [--gdb PORT] [--trace FILE]");
+    };
+    let buf = fs::read(&rom).with_context(|| format!("Failed to read {}", rom))?;
+
+    let mut c8 = Cpu::init();
+    c8.load(&buf).with_context(|| format!("Failed to load {}", rom))?;
+    let platform = Platform::detect(&buf);
+    c8.set_quirks(platform.quirks());
+    let ipf = ipf.unwrap_or(platform.instructions_per_frame() as u64);
+
+    if let Some(path) = &trace_file {
+        let tracer = Tracer::to_file(path, trace_format).with_context(|| format!("Failed to create {}", path))?;
+        c8.set_tracer(Some(tracer.with_filter(trace_filter)));
+    }
+    if profile.is_some() {
+        c8.set_profiler(Some(Profiler::new()));
+    }
+
+    let mut recorder = match &gif_file {
+        Some(path) => Some(GifRecorder::create(path, &palette, scale).with_context(|| format!("Failed to create {}", path))?),
+        None => None,
+    };
+
+    if let Some(port) = gdb_port {
+        run_gdb(&mut c8, port)?;
+    } else {
+        run(&mut c8, cycles, ipf, |c8, frame| match &mut recorder {
+            Some(recorder) if gif_frames.contains(&frame) => recorder.frame(c8),
+            _ => Ok(()),
+        })
+        .context("Failed to record GIF")?;
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish().context("Failed to write GIF")?;
+    }
+
+    if let Some(tracer) = c8.set_tracer(None) {
+        tracer.finish().context("Failed to write trace")?;
+    }
+
+    if let Some(path) = &coverage_file {
+        c8.coverage().save(path).with_context(|| format!("Failed to write {}", path))?;
+    }
+    if let Some(path) = &screenshot {
+        let image = Image::capture(&c8, &palette).scaled(scale);
+        image.save_png(path).with_context(|| format!("Failed to write {}", path))?;
+    }
+    if let (Some(n), Some(profiler)) = (profile, c8.profiler()) {
+        println!("{}", profiler.report(c8.memory(), n));
+    }
+
+    if let Some(fault) = c8.fault() {
+        println!("{}", fault);
+    }
+    print_display(&c8);
+    println!("V: {:02X?}", c8.v);
+    println!("DT: {} ST: {}", c8.dt, c8.st);
+
+    Ok(())
+}
+
+// Calls `on_frame` with the frame number at the end of every frame
+fn run(c8: &mut Cpu, cycles: u64, ipf: u64, mut on_frame: impl FnMut(&Cpu, u64) -> std::io::Result<()>) -> std::io::Result<()> {
+    for i in 0..cycles {
+        if c8.halted() || c8.fault().is_some() {
+            break;
+        }
+        c8.cycle();
+        if (i + 1) % ipf == 0 {
+            c8.tick_timers();
+            on_frame(c8, i / ipf)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gdb")]
+fn run_gdb(c8: &mut Cpu, port: u16) -> Result<()> {
+    let mut debugger = ultra8::Debugger::new();
+    println!("Waiting for gdb on 127.0.0.1:{}", port);
+    ultra8::gdb::listen(("127.0.0.1", port), c8, &mut debugger).context("gdb session failed")
+}
+
+#[cfg(not(feature = "gdb"))]
+fn run_gdb(_c8: &mut Cpu, _port: u16) -> Result<()> {
+    bail!("ultra8-headless was built without the gdb feature");
+}
+
+fn print_display(c8: &Cpu) {
+    let width = c8.width();
+    for row in c8.get_graphics().chunks(width) {
+        let line: String = row.iter().map(|&p| if p != 0 { '#' } else { '.' }).collect();
+        println!("{}", line);
+    }
+}
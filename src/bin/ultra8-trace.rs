@@ -0,0 +1,25 @@
+// Prints a binary execution trace as text
+//
+// usage: ultra8-trace <trace file>
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use anyhow::{bail, Context, Result};
+use ultra8::trace;
+
+fn main() -> Result<()> {
+    let Some(path) = env::args().nth(1) else {
+        bail!("usage: ultra8-trace <trace file>");
+    };
+    let file = File::open(&path).with_context(|| format!("Failed to open {}", path))?;
+    let entries = trace::read_binary(BufReader::new(file)).with_context(|| format!("Failed to read {}", path))?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for entry in entries {
+        writeln!(out, "{}", entry.to_text())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// Screenshots and GIF recordings of the framebuffer
+//
+// Captures are rendered with a palette, optionally scaled up by a whole number so they look like
+// the emulator window.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::cpu::{Cpu, HIRES_HEIGHT, HIRES_WIDTH};
+use crate::palette::Palette;
+
+/// An RGBA image, 4 bytes per pixel row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+	pub width: usize,
+	pub height: usize,
+	pub rgba: Vec<u8>,
+}
+
+impl Image {
+	/// The visible framebuffer at native resolution
+	pub fn capture(cpu: &Cpu, palette: &Palette) -> Image {
+		let (width, height) = (cpu.width(), cpu.height());
+		let mut rgba = vec![0; width * height * 4];
+		cpu.render_rgba(palette, &mut rgba);
+		Image { width, height, rgba }
+	}
+
+	/// Every pixel becomes a `scale` by `scale` block
+	pub fn scaled(&self, scale: usize) -> Image {
+		let scale = scale.max(1);
+		let width = self.width * scale;
+		let mut rgba = Vec::with_capacity(self.rgba.len() * scale * scale);
+		for row in self.rgba.chunks_exact(self.width * 4) {
+			let mut line = Vec::with_capacity(width * 4);
+			for pixel in row.chunks_exact(4) {
+				for _ in 0..scale {
+					line.extend_from_slice(pixel);
+				}
+			}
+			for _ in 0..scale {
+				rgba.extend_from_slice(&line);
+			}
+		}
+		Image { width, height: self.height * scale, rgba }
+	}
+
+	pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let file = BufWriter::new(File::create(path)?);
+		let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+		encoder.set_color(png::ColorType::RGBA);
+		encoder.set_depth(png::BitDepth::Eight);
+		let mut writer = encoder.write_header()?;
+		writer.write_image_data(&self.rgba)?;
+		Ok(())
+	}
+}
+
+/// Records one frame per 60hz tick into an animated GIF
+///
+/// Identical consecutive frames are merged into one longer frame. The GIF is always the hires
+/// size, lores frames are doubled.
+pub struct GifRecorder<W: Write> {
+	encoder: gif::Encoder<W>,
+	scale: usize,
+	// Frame waiting to be written and how many ticks it has lasted so far
+	pending: Option<(Vec<u8>, u64)>,
+	// Ticks written so far, used to round delays without drifting
+	ticks: u64,
+	frames: u64,
+}
+
+impl GifRecorder<BufWriter<File>> {
+	pub fn create(path: impl AsRef<Path>, palette: &Palette, scale: usize) -> io::Result<Self> {
+		GifRecorder::new(BufWriter::new(File::create(path)?), palette, scale)
+	}
+}
+
+impl<W: Write> GifRecorder<W> {
+	pub fn new(out: W, palette: &Palette, scale: usize) -> io::Result<Self> {
+		let scale = scale.max(1);
+		let colors: Vec<u8> = palette.colors.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+		let (width, height) = ((HIRES_WIDTH * scale) as u16, (HIRES_HEIGHT * scale) as u16);
+		let mut encoder = gif::Encoder::new(out, width, height, &colors).map_err(gif_error)?;
+		encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+		Ok(GifRecorder { encoder, scale, pending: None, ticks: 0, frames: 0 })
+	}
+
+	/// Adds the current framebuffer as the next 60hz frame
+	pub fn frame(&mut self, cpu: &Cpu) -> io::Result<()> {
+		self.frames += 1;
+		let pixels = self.indexed(cpu);
+		match &mut self.pending {
+			Some((last, ticks)) if *last == pixels => *ticks += 1,
+			_ => {
+				self.flush()?;
+				self.pending = Some((pixels, 1));
+			}
+		}
+		Ok(())
+	}
+
+	/// Number of 60hz frames recorded
+	pub fn frames(&self) -> u64 {
+		self.frames
+	}
+
+	/// Writes the last frame and the end of the GIF
+	pub fn finish(mut self) -> io::Result<W> {
+		self.flush()?;
+		self.encoder.into_inner()
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		let Some((pixels, ticks)) = self.pending.take() else {
+			return Ok(());
+		};
+		// GIF delays are in hundredths of a second
+		let start = self.ticks * 100 / 60;
+		self.ticks += ticks;
+		let delay = (self.ticks * 100 / 60 - start).min(u16::MAX as u64) as u16;
+		let size = self.scale;
+		let frame = gif::Frame {
+			width: (HIRES_WIDTH * size) as u16,
+			height: (HIRES_HEIGHT * size) as u16,
+			delay,
+			buffer: pixels.into(),
+			..Default::default()
+		};
+		self.encoder.write_frame(&frame).map_err(gif_error)
+	}
+
+	// Palette indices of the framebuffer scaled to the GIF size
+	fn indexed(&self, cpu: &Cpu) -> Vec<u8> {
+		let pixels = cpu.get_graphics();
+		let width = cpu.width();
+		let scale = self.scale * HIRES_WIDTH / width;
+		let mut out = Vec::with_capacity(HIRES_WIDTH * HIRES_HEIGHT * self.scale * self.scale);
+		for row in pixels.chunks_exact(width) {
+			let line: Vec<u8> = row.iter().flat_map(|&p| std::iter::repeat_n(p & 0x3, scale)).collect();
+			for _ in 0..scale {
+				out.extend_from_slice(&line);
+			}
+		}
+		out
+	}
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+	match e {
+		gif::EncodingError::Io(e) => e,
+		e => io::Error::new(io::ErrorKind::InvalidData, e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn scaling_repeats_pixels() {
+		let image = Image { width: 2, height: 1, rgba: vec![1, 1, 1, 1, 2, 2, 2, 2] };
+		let scaled = image.scaled(2);
+		assert_eq!((scaled.width, scaled.height), (4, 2));
+		let row = [1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2];
+		assert_eq!(scaled.rgba[..16], row);
+		assert_eq!(scaled.rgba[16..], row);
+	}
+
+	#[test]
+	fn gif_merges_repeated_frames() {
+		let mut cpu = Cpu::init();
+		let mut recorder = GifRecorder::new(Vec::new(), &Palette::CLASSIC, 1).unwrap();
+		for _ in 0..3 {
+			recorder.frame(&cpu).unwrap();
+		}
+		cpu.display[0] = 1;
+		recorder.frame(&cpu).unwrap();
+		assert_eq!(recorder.frames(), 4);
+		let gif = recorder.finish().unwrap();
+
+		let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+		let first = decoder.read_next_frame().unwrap().unwrap();
+		// Three ticks are 5 hundredths of a second
+		assert_eq!((first.width, first.height, first.delay), (128, 64, 5));
+		let second = decoder.read_next_frame().unwrap().unwrap();
+		// Lores pixels are doubled
+		assert_eq!(second.buffer[..3], [1, 1, 0]);
+		assert_eq!(second.buffer[128..131], [1, 1, 0]);
+		assert!(decoder.read_next_frame().unwrap().is_none());
+	}
+
+	#[test]
+	fn png_round_trip() {
+		let mut cpu = Cpu::init();
+		cpu.display[1] = 1;
+		let path = std::env::temp_dir().join(format!("ultra8-capture-{}.png", std::process::id()));
+		Image::capture(&cpu, &Palette::CLASSIC).scaled(3).save_png(&path).unwrap();
+
+		let decoder = png::Decoder::new(File::open(&path).unwrap());
+		let (info, mut reader) = decoder.read_info().unwrap();
+		let mut data = vec![0; info.buffer_size()];
+		reader.next_frame(&mut data).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!((info.width, info.height), (64 * 3, 32 * 3));
+		assert_eq!(data[..4], Palette::CLASSIC.colors[0]);
+		assert_eq!(data[12..16], Palette::CLASSIC.colors[1]);
+	}
+}
@@ -0,0 +1,169 @@
+// Code and data coverage of the address space
+//
+// Every Cpu keeps a coverage map with one byte of flags per address of memory. Instruction
+// fetches mark bytes as executed and the data accesses reported by `Cpu::last_accesses` mark them
+// as read or written, so bytes that were read but never executed are sprites or tables.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::cpu::MEMSIZE;
+
+/// The byte was fetched as part of an instruction
+pub const EXECUTED: u8 = 0x1;
+/// The byte was read as data, by DXYN, FX65, 5XY3 or F002
+pub const READ: u8 = 0x2;
+/// The byte was written, by FX33, FX55 or 5XY2
+pub const WRITTEN: u8 = 0x4;
+
+#[derive(Debug, Clone)]
+pub struct Coverage {
+	flags: Vec<u8>,
+}
+
+impl Default for Coverage {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Coverage {
+	pub fn new() -> Self {
+		Coverage { flags: vec![0; MEMSIZE] }
+	}
+
+	pub(crate) fn mark(&mut self, addr: u16, len: u16, flag: u8) {
+		for i in 0..len {
+			self.flags[addr.wrapping_add(i) as usize] |= flag;
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.flags.fill(0);
+	}
+
+	/// The flags of `addr`, a combination of [`EXECUTED`], [`READ`] and [`WRITTEN`]
+	pub fn flags(&self, addr: u16) -> u8 {
+		self.flags[addr as usize]
+	}
+
+	pub fn executed(&self, addr: u16) -> bool {
+		self.flags(addr) & EXECUTED != 0
+	}
+
+	pub fn read(&self, addr: u16) -> bool {
+		self.flags(addr) & READ != 0
+	}
+
+	pub fn written(&self, addr: u16) -> bool {
+		self.flags(addr) & WRITTEN != 0
+	}
+
+	/// True for bytes that were read as data and never executed
+	pub fn is_data(&self, addr: u16) -> bool {
+		self.flags(addr) & (EXECUTED | READ) == READ
+	}
+
+	/// The raw map, one byte of flags per address, this is the exported file format
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.flags
+	}
+
+	/// Runs of consecutive addresses with the same flags, untouched memory is left out
+	pub fn ranges(&self) -> Vec<(RangeInclusive<u16>, u8)> {
+		let mut ranges: Vec<(RangeInclusive<u16>, u8)> = Vec::new();
+		for (addr, &flags) in self.flags.iter().enumerate() {
+			if flags == 0 {
+				continue;
+			}
+			let addr = addr as u16;
+			match ranges.last_mut() {
+				Some((range, last)) if *last == flags && range.end().wrapping_add(1) == addr => {
+					*range = *range.start()..=addr;
+				}
+				_ => ranges.push((addr..=addr, flags)),
+			}
+		}
+		ranges
+	}
+
+	/// A text summary with one line per range, e.g. `0200-0229 code`
+	pub fn to_text(&self) -> String {
+		let mut out = String::new();
+		for (range, flags) in self.ranges() {
+			let _ = writeln!(out, "{:04X}-{:04X} {}", range.start(), range.end(), describe(flags));
+		}
+		out
+	}
+
+	/// Writes the map to `path`, as the [`Coverage::to_text`] summary if it ends in `.txt` and as
+	/// the raw map otherwise
+	pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let path = path.as_ref();
+		if path.extension().is_some_and(|ext| ext == "txt") {
+			fs::write(path, self.to_text())
+		} else {
+			fs::write(path, self.as_bytes())
+		}
+	}
+}
+
+// Names the flags, e.g. "code", "data" or "data written"
+fn describe(flags: u8) -> String {
+	let mut parts = Vec::new();
+	if flags & EXECUTED != 0 {
+		parts.push("code");
+	}
+	if flags & READ != 0 {
+		parts.push("data");
+	}
+	if flags & WRITTEN != 0 {
+		parts.push("written");
+	}
+	parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cpu::{cpu_with, Cpu};
+
+	// LD I, 0x20A / DRW V0, V0, 2 / LD I, 0x300 / LD B, V0 / JP 0x208 / sprite
+	fn covered(cycles: usize) -> Cpu {
+		let mut cpu = cpu_with(&[0xA20A, 0xD002, 0xA300, 0xF033, 0x1208, 0xFF81]);
+		for _ in 0..cycles {
+			cpu.cycle();
+		}
+		cpu
+	}
+
+	#[test]
+	fn marks_code_data_and_writes() {
+		let cpu = covered(6);
+		let coverage = cpu.coverage();
+		assert!(coverage.executed(0x200) && coverage.executed(0x201));
+		assert!(coverage.is_data(0x20A) && coverage.is_data(0x20B));
+		assert!(!coverage.is_data(0x200));
+		assert_eq!(coverage.flags(0x300), WRITTEN);
+		assert_eq!(coverage.flags(0x303), 0);
+	}
+
+	#[test]
+	fn ranges_merge_equal_flags() {
+		let cpu = covered(6);
+		assert_eq!(
+			cpu.coverage().to_text(),
+			"0200-0209 code\n020A-020B data\n0300-0302 written\n"
+		);
+	}
+
+	#[test]
+	fn clear_forgets_everything() {
+		let mut cpu = covered(6);
+		cpu.clear_coverage();
+		assert!(cpu.coverage().ranges().is_empty());
+	}
+}
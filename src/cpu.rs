@@ -1,13 +1,37 @@
-use std::thread;
-use std::time::Duration;
+#![allow(non_snake_case)]
+
+use std::fmt;
+
 use rand::Rng;
 
-const MEMSIZE: usize = 4096;
+use crate::coverage::{self, Coverage};
+use crate::palette::Palette;
+use crate::disasm;
+use crate::profile::Profiler;
+use crate::quirks::Quirks;
+use crate::trace::{TraceEntry, Tracer};
+
+/// Size of the address space. XO-Chip extends it to 64k, Chip8 programs only ever use the first 4k
+pub const MEMSIZE: usize = 0x10000;
+
+/// Where roms are loaded and execution starts
+pub const PROGRAM_START: usize = 0x200;
 
-const PROGRAM_START: usize = 0x200;
+/// Largest rom that fits between [`PROGRAM_START`] and the end of memory
+pub const MAX_ROM_LEN: usize = MEMSIZE - PROGRAM_START;
 
-const SCREEN_WIDTH: usize = 64;
-const SCREEN_HEIGHT: usize = 32;
+/// Low resolution display size
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+/// Super chip 8 high resolution display size
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Location of the 4x5 hex font
+pub const FONT_START: usize = 0x00;
+/// Location of the 8x10 super chip font
+pub const BIG_FONT_START: usize = 0x50;
 
 const FONTS: [u8; 80] = [
   0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -28,12 +52,100 @@ const FONTS: [u8; 80] = [
   0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
-#[derive(Debug)]
-enum video_mode {
-	SCHIP8,
-	CHIP8,
+// 8x10 fonts used by FX30, the A-F glyphs are an XO-Chip addition
+const BIG_FONTS: [u8; 160] = [
+  0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+  0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+  0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+  0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+  0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+  0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+  0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+  0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+  0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+  0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+  0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+  0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+  0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+	Read,
+	Write,
+}
+
+/// A data access made by an instruction, `len` bytes starting at `addr` wrapping at the end of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+	pub kind: AccessKind,
+	pub addr: u16,
+	pub len: u16,
+}
+
+impl MemAccess {
+	/// Every address touched by the access
+	pub fn addresses(&self) -> impl Iterator<Item = u16> {
+		let addr = self.addr;
+		(0..self.len).map(move |i| addr.wrapping_add(i))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoMode {
+	Lores,
+	Hires,
+}
+
+/// The registers of a [`Cpu`], to carry them over to another one with [`Cpu::set_registers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+	pub v: [u8; 16],
+	pub index: u16,
+	pub pc: u16,
+	pub sp: u16,
+	pub stack: [u16; 16],
+	pub dt: u8,
+	pub st: u8,
+}
+
+/// Returned by [`Cpu::load`] for a rom that doesn't fit in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomTooLarge {
+	pub len: usize,
+}
+
+impl fmt::Display for RomTooLarge {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Rom is {} bytes, only {} fit in memory", self.len, MAX_ROM_LEN)
+	}
+}
+
+impl std::error::Error for RomTooLarge {}
+
+/// Why a [`Cpu`] stopped executing without the program asking to, see [`Cpu::fault`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+	/// The word at `pc` is not an instruction
+	InvalidOpcode { pc: u16, opcode: u16 },
+	/// A CALL at `pc` with all 16 stack entries in use
+	StackOverflow { pc: u16 },
+	/// A RET at `pc` with nothing on the stack
+	StackUnderflow { pc: u16 },
 }
 
+impl fmt::Display for Fault {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Fault::InvalidOpcode { pc, opcode } => write!(f, "Invalid opcode {:04X} at {:04X}", opcode, pc),
+			Fault::StackOverflow { pc } => write!(f, "Stack overflow at {:04X}", pc),
+			Fault::StackUnderflow { pc } => write!(f, "Stack underflow at {:04X}", pc),
+		}
+	}
+}
 
 pub struct Cpu {
 	pub v: [u8; 16],
@@ -46,18 +158,55 @@ pub struct Cpu {
 
 	opcode: u16,
 
-	pub display: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+	// Each pixel holds one bit per XO-Chip bitplane, lores only uses the first 64 * 32 entries
+	pub display: [u8; HIRES_WIDTH * HIRES_HEIGHT],
 
 	pub keypad: [u8; 16],
 	pub keypressed: bool,
 
 	pub draw: bool,
 
-	vmode: video_mode,
+	vmode: VideoMode,
 
 	stack: [u16; 16],
 
 	pub dt: u8,
+	pub st: u8,
+
+	// Super chip 8 RPL user flags, XO-Chip allows all 16 to be saved
+	rpl: [u8; 16],
+
+	// XO-Chip state
+	planes: u8,
+	audio_pattern: [u8; 16],
+	pitch: u8,
+
+	// Set by 00FD, the cpu stops executing instructions
+	halted: bool,
+	// Set instead of executing something that can't be, the cpu stops until a reset
+	fault: Option<Fault>,
+
+	// Data reads and writes made by the last instruction, instruction fetches are not included
+	accesses: Vec<MemAccess>,
+
+	// Copy of the loaded rom for hard resets
+	rom: Vec<u8>,
+
+	tracer: Option<Tracer>,
+	profiler: Option<Profiler>,
+	coverage: Coverage,
+
+	quirks: Quirks,
+	// Progress of an FX0A that has not finished yet
+	key_wait: Option<KeyWait>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyWait {
+	// Keys that were already held when the wait started, one bit per key
+	ignored: u16,
+	// Key pressed during the wait, the wait ends once it is released
+	pressed: Option<u8>,
 }
 
 impl Cpu {
@@ -73,50 +222,138 @@ impl Cpu {
 
 			index: 0,
 
-			display: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
-			vmode: video_mode::CHIP8,
+			display: [0; HIRES_WIDTH * HIRES_HEIGHT],
+			vmode: VideoMode::Lores,
 			keypad: [0; 16],
 			keypressed: false,
 			stack: [0;16],
 			draw: false,
 
 			dt: 0,
+			st: 0,
+
+			rpl: [0; 16],
+
+			planes: 1,
+			audio_pattern: [0; 16],
+			pitch: 64,
+
+			halted: false,
+			fault: None,
+
+			accesses: Vec::new(),
+
+			rom: Vec::new(),
+
+			tracer: None,
+			profiler: None,
+			coverage: Coverage::new(),
+
+			quirks: Quirks::default(),
+			key_wait: None,
 		}
 	}
 
-	pub fn load(&mut self, content: &[u8]) {
-		// Load rom into memory
-		for i in 0..content.len() {
-			self.mem[i + PROGRAM_START] = content[i];
-			println!("{}", content[i]);
+	/// Copies the rom to [`PROGRAM_START`] and the fonts to the start of memory
+	pub fn load(&mut self, content: &[u8]) -> Result<(), RomTooLarge> {
+		if content.len() > MAX_ROM_LEN {
+			return Err(RomTooLarge { len: content.len() });
 		}
+		self.install(content.to_vec());
+		Ok(())
+	}
+
+	fn install(&mut self, rom: Vec<u8>) {
+		// Load rom into memory
+		self.mem[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(&rom);
+		self.rom = rom;
 
 		// Also loads fonts aswell
-		for i in 0..FONTS.len() {
-			self.mem[i] = FONTS[i];
-		}
+		self.mem[FONT_START..FONT_START + FONTS.len()].copy_from_slice(&FONTS);
+		self.mem[BIG_FONT_START..BIG_FONT_START + BIG_FONTS.len()].copy_from_slice(&BIG_FONTS);
+	}
+
+	/// Restarts the program without touching memory: registers, stack, timers and the display are
+	/// cleared and execution goes back to [`PROGRAM_START`]
+	pub fn soft_reset(&mut self) {
+		self.v = [0; 16];
+		self.index = 0;
+		self.pc = PROGRAM_START as u16;
+		self.sp = 0;
+		self.stack = [0; 16];
+		self.opcode = 0;
+		self.dt = 0;
+		self.st = 0;
+		self.vmode = VideoMode::Lores;
+		self.display = [0; HIRES_WIDTH * HIRES_HEIGHT];
+		self.draw = true;
+		self.planes = 1;
+		self.halted = false;
+		self.fault = None;
+		self.key_wait = None;
+		self.accesses.clear();
+	}
+
+	/// Restarts as if the machine was switched off and on: memory is cleared and the rom and fonts
+	/// are loaded again. Quirks, coverage and any tracer or profiler are kept
+	pub fn hard_reset(&mut self) {
+		let rom = std::mem::take(&mut self.rom);
+		self.mem.fill(0);
+		self.rpl = [0; 16];
+		self.audio_pattern = [0; 16];
+		self.pitch = 64;
+		self.soft_reset();
+		self.install(rom);
 	}
 
 	fn fetch(&mut self) {
-		let a = self.mem[self.pc as usize];
-		let b = self.mem[(self.pc + 1) as usize];
+		self.opcode = self.read_word(self.pc);
+	}
+
+	/// Reads the big endian instruction word at `addr`
+	pub fn read_word(&self, addr: u16) -> u16 {
+		let a = self.mem[addr as usize];
+		let b = self.mem[addr.wrapping_add(1) as usize];
 
-		self.opcode = (((a as u16) << 8) | b as u16) as u16;
+		((a as u16) << 8) | b as u16
 	}
 
+	/// Fetches and executes a single instruction
 	pub fn cycle(&mut self) {
+		if self.halted || self.fault.is_some() {
+			return;
+		}
+
+		self.accesses.clear();
 		self.fetch();
+		if self.tracer.is_some() {
+			let entry = self.trace_entry();
+			if let Some(tracer) = &mut self.tracer {
+				tracer.record(&entry);
+			}
+		}
+		if let Some(profiler) = &mut self.profiler {
+			profiler.record(self.pc, self.opcode);
+		}
+		self.coverage.mark(self.pc, disasm::instruction_len(self.opcode), coverage::EXECUTED);
 		// println!("OP: {:#2x}", self.opcode);
 		match self.opcode & 0xF000 {
 			0x0000 => {
-				match self.opcode & 0x00FF {
-					0xE0 => self.ins_0x00E0(),
-					0xEE => self.ins_0x00EE(),
-					// S-CHIP Instructions
-					0xFD => self.ins_0x00FD(),
-					0xFE => self.ins_0x00FE(),
-					0xFF => self.ins_0x00FF(),
-					_ => unimplemented!("Opcode {:#2x}", self.opcode),
+				match self.opcode & 0x00F0 {
+					// S-CHIP / XO-CHIP scrolling
+					0xC0 => self.ins_0x00CN(),
+					0xD0 => self.ins_0x00DN(),
+					_ => match self.opcode & 0x00FF {
+						0xE0 => self.ins_0x00E0(),
+						0xEE => self.ins_0x00EE(),
+						// S-CHIP Instructions
+						0xFB => self.ins_0x00FB(),
+						0xFC => self.ins_0x00FC(),
+						0xFD => self.ins_0x00FD(),
+						0xFE => self.ins_0x00FE(),
+						0xFF => self.ins_0x00FF(),
+						_ => self.invalid_opcode(),
+					}
 				}
 			}
 
@@ -124,7 +361,15 @@ impl Cpu {
 			0x2000 => self.ins_0x2000(),
 			0x3000 => self.ins_0x3000(),
 			0x4000 => self.ins_0x4000(),
-			0x5000 => self.ins_0x5000(),
+			0x5000 => {
+				match self.opcode & 0x000F {
+					0x0 => self.ins_0x5000(),
+					// XO-CHIP Instructions
+					0x2 => self.ins_05XY2(),
+					0x3 => self.ins_05XY3(),
+					_ => self.invalid_opcode(),
+				}
+			}
 			0x6000 => self.ins_0x6000(),
 			0x7000 => self.ins_0x7000(),
 			0x9000 => self.ins_0x9000(),
@@ -133,35 +378,45 @@ impl Cpu {
 			0xB000 => self.ins_0xB000(),
 			0xC000 => {
 				let mut rng = rand::thread_rng();
-				self.v[self.get_vx() as usize] = rng.gen_range(0..255) & self.get_nn();
-				self.pc += 2;
+				self.v[self.get_vx() as usize] = rng.gen::<u8>() & self.get_nn();
+				self.pc = self.pc.wrapping_add(2);
 			}
 			0xD000 => self.ins_D000(),
 			0xE000 => {
 				match self.opcode & 0x00FF {
 					0x9E => self.ins_0xE9E(),
 					0xA1 => self.ins_0xEXA1(),
-					_ => unimplemented!("Opcode {:#2x}", self.opcode),
+					_ => self.invalid_opcode(),
 				}
 			}
 			0xF000 => {
 				match self.opcode & 0x00FF {
+					0x00 if self.opcode == 0xF000 => self.ins_0xF000(),
+					0x01 => self.ins_0xFN01(),
+					0x02 if self.opcode == 0xF002 => self.ins_0xF002(),
 					0x07 => {
 						self.v[self.get_vx() as usize] = self.dt;
-						self.pc += 2;
+						self.pc = self.pc.wrapping_add(2);
+					}
+					0x18 => {
+						self.st = self.v[self.get_vx() as usize];
+						self.pc = self.pc.wrapping_add(2);
 					}
-					0x18 => self.pc += 2,
 					0x29 => self.ins_0xF029(),
+					0x30 => self.ins_0xF030(),
 					0x33 => self.ins_F033(),
+					0x3A => self.ins_0xF03A(),
 					0x55 => self.ins_F055(),
 					0x65 => self.ins_F065(),
+					0x75 => self.ins_0xF075(),
+					0x85 => self.ins_0xF085(),
 					0x1E => self.ins_0xF01E(),
 					0x0A => self.ins_0xF00A(),
 					0x15 => {
-						self.dt = self.get_vx() as u8;
-						self.pc += 2;
+						self.dt = self.v[self.get_vx() as usize];
+						self.pc = self.pc.wrapping_add(2);
 					}
-					_ => unimplemented!("Opcode {:#2x}", self.opcode),
+					_ => self.invalid_opcode(),
 				}
 			}
 			0x8000 => {
@@ -175,11 +430,11 @@ impl Cpu {
 					0x6 => self.ins_0x8006(),
 					0x7 => self.ins_0x8007(),
 					0xE => self.ins_0x800E(),
-					_ => unimplemented!("Opcode {:#2x}", self.opcode),
+					_ => self.invalid_opcode(),
 				}
 			}
 
-			_ => unimplemented!("Opcode {:#2x}", self.opcode),
+			_ => self.invalid_opcode(),
 		}
 
 
@@ -192,7 +447,7 @@ impl Cpu {
 	}
 
 	fn get_nn(&self) -> u8 {
-		(self.opcode & 0x00FF).try_into().unwrap()
+		(self.opcode & 0x00FF) as u8
 	}
 
 	fn get_n(&self) -> u16 {
@@ -206,18 +461,43 @@ impl Cpu {
 	fn get_vy(&self) -> u16 {
 		(self.opcode & 0x00F0) >> 4
 	}
+
+	// Leaves pc on the opcode so the fault points at it
+	fn invalid_opcode(&mut self) {
+		self.fault = Some(Fault::InvalidOpcode { pc: self.pc, opcode: self.opcode });
+	}
+
+	// Skips the next instruction, F000 NNNN is four bytes long so it has to be skipped whole
+	fn skip_next(&mut self) {
+		self.pc = self.pc.wrapping_add(2);
+		if self.read_word(self.pc) == 0xF000 {
+			self.pc = self.pc.wrapping_add(4);
+		} else {
+			self.pc = self.pc.wrapping_add(2);
+		}
+	}
 }
 
 // Stack functions
 impl Cpu {
-	fn stack_pop(&mut self) -> u16 {
+	// Both fault instead of going past either end of the stack
+	fn stack_pop(&mut self) -> Option<u16> {
+		if self.sp == 0 {
+			self.fault = Some(Fault::StackUnderflow { pc: self.pc });
+			return None;
+		}
 		self.sp -= 1;
-		self.stack[self.sp as usize]
+		Some(self.stack[self.sp as usize])
 	}
 
-	fn stack_push(&mut self, value: u16) {
+	fn stack_push(&mut self, value: u16) -> Option<()> {
+		if self.sp as usize == self.stack.len() {
+			self.fault = Some(Fault::StackOverflow { pc: self.pc });
+			return None;
+		}
 		self.stack[self.sp as usize] = value;
 		self.sp += 1;
+		Some(())
 	}
 }
 
@@ -225,14 +505,18 @@ impl Cpu {
 
 impl Cpu {
 	fn ins_0x00E0(&mut self) {
-		self.display = [0; 64 * 32];
+		// Only the selected XO-Chip planes get cleared
+		for pixel in self.display.iter_mut() {
+			*pixel &= !self.planes;
+		}
 		self.draw = true;
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x00EE(&mut self) {
-		self.pc = self.stack_pop();
-		self.pc += 2;
+		if let Some(addr) = self.stack_pop() {
+			self.pc = addr.wrapping_add(2);
+		}
 	}
 
 
@@ -242,24 +526,25 @@ impl Cpu {
 	}
 
 	fn ins_0x2000(&mut self) {
-		self.stack_push(self.pc);
-
-		self.pc = self.get_nnn();
+		if self.stack_push(self.pc).is_some() {
+			self.pc = self.get_nnn();
+		}
 	}
 
 	fn ins_0x3000(&mut self) {
 		if self.v[self.get_vx() as usize] == self.get_nn() {
-			self.pc += 4;
+			self.skip_next();
 		} else {
-			self.pc += 2;
+			self.pc = self.pc.wrapping_add(2);
 		}
 	}
 
 	fn ins_0x4000(&mut self) {
 		if self.v[self.get_vx() as usize] != self.get_nn() {
-			self.pc += 2;
+			self.skip_next();
+		} else {
+			self.pc = self.pc.wrapping_add(2);
 		}
-		self.pc += 2;
 	}
 
 	fn ins_0x5000(&mut self) {
@@ -267,26 +552,25 @@ impl Cpu {
 		let vy = self.v[self.get_vy() as usize];
 
 		if vx == vy {
-			self.pc += 4;
+			self.skip_next();
 		} else {
-			self.pc += 2;
+			self.pc = self.pc.wrapping_add(2);
 		}
 	}
 
 	fn ins_0x6000(&mut self) {
 		self.v[self.get_vx() as usize] = self.get_nn();
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x7000(&mut self) {
-		//self.v[self.get_vx() as usize] += self.get_nn();
 		self.v[self.get_vx() as usize] = self.v[self.get_vx() as usize].wrapping_add(self.get_nn());
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x8000(&mut self) {
 		self.v[self.get_vx() as usize] = self.v[self.get_vy() as usize];
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x8001(&mut self) {
@@ -294,7 +578,7 @@ impl Cpu {
 
 		self.v[self.get_vx() as usize] |= vy;
 
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x8002(&mut self) {
@@ -302,7 +586,7 @@ impl Cpu {
 
 		self.v[self.get_vx() as usize] &= vy;
 
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x8003(&mut self) {
@@ -310,212 +594,209 @@ impl Cpu {
 
 		self.v[self.get_vx() as usize] ^= vy;
 
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
+	// The flag is always written after the result so VF ends up holding the flag
+	// when it is also the destination register
+
 	fn ins_0x8004(&mut self) {
-		// We convert the result in 16 bits to detect if it goes past 8 bits, and if it does
-		// set the appropriate flag
-		let result = (self.v[self.get_vx() as usize]) as u16 + (self.v[self.get_vy() as usize]) as u16;
-		if result > 255 {
-			self.v[0xF] = 1;
-		} else {
-			self.v[0xF] = 0;
-		}
-		self.v[self.get_vx() as usize] = result as u8;
-		self.pc += 2;
+		let vx = self.get_vx() as usize;
+		let vy = self.get_vy() as usize;
+		let (result, carry) = self.v[vx].overflowing_add(self.v[vy]);
+		self.v[vx] = result;
+		self.v[0xF] = carry as u8;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x8005(&mut self) {
-		//let result = (self.v[self.get_vx() as usize]) as u16 - (self.v[self.get_vy() as usize]) as u16;
-		let vy = self.v[self.get_vy() as usize];
-		if (self.v[self.get_vx() as usize]) > (self.v[self.get_vy() as usize]) {
-			self.v[0xF] = 1;
-		} else {
-			self.v[0xF] = 0;
-		}
-
-		self.v[self.get_vx() as usize] = self.v[self.get_vx() as usize].wrapping_sub(vy);
-
-		self.pc += 2;
+		// VF is set when there is no borrow, which includes VX == VY
+		let vx = self.get_vx() as usize;
+		let vy = self.get_vy() as usize;
+		let (result, borrow) = self.v[vx].overflowing_sub(self.v[vy]);
+		self.v[vx] = result;
+		self.v[0xF] = !borrow as u8;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x8006(&mut self) {
 		let vx = self.get_vx() as usize;
-		let vy = self.get_vy() as usize;
 
-		self.v[0xF] = self.v[vx] & 0x1;
+		let flag = self.v[vx] & 0x1;
 		self.v[vx] >>= 1;
-		self.v[vx] = self.v[vx] as u8;
-		self.pc += 2;
+		self.v[0xF] = flag;
+		self.pc = self.pc.wrapping_add(2);
 
 	}
 
 	fn ins_0x8007(&mut self) {
 		let vx = self.get_vx() as usize;
 		let vy = self.get_vy() as usize;
-		let (result, overflow) = self.v[vy].overflowing_sub(self.v[vx]);
+		let (result, borrow) = self.v[vy].overflowing_sub(self.v[vx]);
 		self.v[vx] = result;
-		match overflow {
-			true => self.v[0xF] = 0,
-			false => self.v[0xF] = 1,
-		}
-		self.pc += 2;
+		self.v[0xF] = !borrow as u8;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x800E(&mut self) {
-		//v[0xF] = (v[x] shr 7)
-		// let vy = self.v[self.get_vy() as usize];
-		// self.v[0xF] = self.v[self.get_vx() as usize] >> 7;
-		// self.v[self.get_vx() as usize] = vy << 1;
-		// self.pc += 2;
 		let vx = self.get_vx() as usize;
-		self.v[0xF] = self.v[vx] >> 7;
-		self.v[vx] = self.v[vx] << 1;
-		self.pc += 2;
+		let flag = self.v[vx] >> 7;
+		self.v[vx] <<= 1;
+		self.v[0xF] = flag;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x9000(&mut self) {
 		let vx = self.v[self.get_vx() as usize];
 		let vy = self.v[self.get_vy() as usize];
 		if vx != vy {
-			self.pc += 4;
+			self.skip_next();
 		} else {
-			self.pc += 2;
+			self.pc = self.pc.wrapping_add(2);
 		}
 
 	}
 
 	fn ins_0xA000(&mut self) {
 		self.index = self.get_nnn();
-		self.pc += 2;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0xB000(&mut self) {
-		self.pc = self.get_nnn() + self.v[0] as u16;
+		self.pc = self.get_nnn().wrapping_add(self.v[0] as u16);
 	}
 
 	fn ins_D000(&mut self) {
-		self.vmode = video_mode::CHIP8;
-
-		let height = self.get_n();
-		let x = self.v[self.get_vx() as usize];
-		let y = self.v[self.get_vy() as usize];
-		//println!("SAD {:?}", self.vmode);
-		match &self.vmode {
-			video_mode::CHIP8 => {
-				// Normal chip 8 rendering
-				self.v[0xF] = 0;
-				for yline in 0..height {
-					let pixel = self.mem[(self.index + yline) as usize];
-					for xline in 0..8 {
-						if pixel & (0x80 >> xline) != 0 {
-							let a = ((x as u16 + xline) as u32) % 64;
-							let b = ((y as u16 + yline) as u32) % 32;
-							if self.display[(a + (b * 64)) as usize] == 1 {
-								self.v[0xF] = 1;
-							}
-							self.display[(a + (b * 64)) as usize] ^= 1;
+		let (width, height) = (self.width(), self.height());
+
+		// The starting position wraps, the sprite itself also wraps around the edges
+		let x = self.v[self.get_vx() as usize] as usize % width;
+		let y = self.v[self.get_vy() as usize] as usize % height;
+
+		// DXY0 draws a 16x16 sprite
+		let (rows, cols) = match self.get_n() {
+			0 => (16, 16),
+			n => (n as usize, 8),
+		};
+		let row_bytes = cols / 8;
+
+		self.v[0xF] = 0;
+		let mut addr = self.index as usize;
+		for plane in 0..2 {
+			let bit = 1 << plane;
+			if self.planes & bit == 0 {
+				continue;
+			}
+
+			// Every selected XO-Chip plane reads its own sprite data, one after the other
+			for yline in 0..rows {
+				for xline in 0..cols {
+					let byte = self.mem[(addr + yline * row_bytes + xline / 8) % MEMSIZE];
+					if byte & (0x80 >> (xline % 8)) != 0 {
+						let a = (x + xline) % width;
+						let b = (y + yline) % height;
+						if self.display[a + b * width] & bit != 0 {
+							self.v[0xF] = 1;
 						}
+						self.display[a + b * width] ^= bit;
 					}
 				}
-				self.draw = true;
-			}
-			video_mode::SCHIP8 => {
-				// Super chip 8 drawing
 			}
+			addr += rows * row_bytes;
 		}
-		// self.v[0xF] = 0;
-		// for yline in 0..height {
-		// 	let pixel = self.mem[(self.index + yline) as usize];
-		// 	for xline in 0..8 {
-		// 		if pixel & (0x80 >> xline) != 0 {
-		// 			let a = ((x as u16 + xline) as u32) % 64;
-		// 			let b = ((y as u16 + yline) as u32) % 32;
-		// 			if self.display[(a + (b * 64)) as usize] == 1 {
-		// 				self.v[0xF] = 1;
-		// 			}
-		// 			self.display[(a + (b * 64)) as usize] ^= 1;
-
-		// 			// let a = ((x as u16).wrapping_add(xline)) as u16;
-		// 			// let b = ((y as u16).wrapping_add(yline)) as u16;
-
-		// 			// if self.display[a.wrapping_add(b.wrapping_mul(64)) as usize] == 1{
-
-		// 			// }
-
-		// 			// self.display[a.wrapping_add(b.wrapping_mul(64)) as usize] ^= 1;
-		// 		}
-		// 	}
-		// }
-		//self.draw = true;
-		self.pc += 2;
+		let len = (addr - self.index as usize) as u16;
+		if len > 0 {
+			self.record(AccessKind::Read, self.index, len);
+		}
+		self.draw = true;
+		self.pc = self.pc.wrapping_add(2);
 	}
+
 	fn ins_0xF029(&mut self) {
-		self.index = (self.v[self.get_vx() as usize] * 0x5) as u16;
-		self.pc += 2;
+		self.index = (FONT_START + (self.v[self.get_vx() as usize] & 0xF) as usize * 5) as u16;
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 
 	fn ins_F033(&mut self) {
 		let vx = self.v[self.get_vx() as usize];
-		let vy = self.v[self.get_vy() as usize];
-		let i = self.index as usize;
+		let i = self.index;
 
-		self.mem[i] = vx / 100;
-		self.mem[i + 1] = (vx / 10) % 10;
-		self.mem[i + 2] = (vx % 100) % 10;
-		self.pc += 2;
+		self.mem[i as usize] = vx / 100;
+		self.mem[i.wrapping_add(1) as usize] = (vx / 10) % 10;
+		self.mem[i.wrapping_add(2) as usize] = vx % 10;
+		self.record(AccessKind::Write, i, 3);
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_F055(&mut self) {
 		for i in 0..self.get_vx() + 1 {
-			self.mem[(self.index + i) as usize] = self.v[i as usize];
-			
+			self.mem[self.index.wrapping_add(i) as usize] = self.v[i as usize];
 		}
-		self.pc += 2;
+		self.record(AccessKind::Write, self.index, self.get_vx() + 1);
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_F065(&mut self) {
 		for i in 0..self.get_vx() + 1 {
-			self.v[i as usize] = self.mem[(self.index + i) as usize];
+			self.v[i as usize] = self.mem[self.index.wrapping_add(i) as usize];
 		}
-		self.pc += 2;
+		self.record(AccessKind::Read, self.index, self.get_vx() + 1);
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0xF01E(&mut self) {
-		self.index += self.v[self.get_vx() as usize] as u16;
-		self.pc += 2;
+		self.index = self.index.wrapping_add(self.v[self.get_vx() as usize] as u16);
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0xE9E(&mut self) {
-		if self.keypad[(self.v[self.get_vx() as usize]) as usize] != 0 {
-			self.pc += 4;
+		if self.keypad[(self.v[self.get_vx() as usize] & 0xF) as usize] != 0 {
+			self.skip_next();
 		} else {
-			self.pc += 2;
+			self.pc = self.pc.wrapping_add(2);
 		}
 	}
 
 	fn ins_0xEXA1(&mut self) {
-		if self.keypad[(self.v[self.get_vx() as usize]) as usize] == 0 {
-			self.pc += 4;
+		if self.keypad[(self.v[self.get_vx() as usize] & 0xF) as usize] == 0 {
+			self.skip_next();
 		} else {
-			self.pc += 2;
+			self.pc = self.pc.wrapping_add(2);
 		}
 	}
 
 	fn ins_0xF00A(&mut self) {
-		self.keypressed = false;
-		let vx = self.get_vx() as usize;
-		for i in 0..16 {
-			if self.keypad[i] == 1 {
-				self.keypressed = true;
-				self.v[vx] = i as u8;
+		let held = (0..16).filter(|&i| self.keypad[i] != 0).fold(0u16, |mask, i| mask | 1 << i);
+		let mut wait = self.key_wait.unwrap_or(KeyWait {
+			ignored: if self.quirks.key_release { held } else { 0 },
+			pressed: None,
+		});
+		wait.ignored &= held;
+
+		let key = match wait.pressed {
+			// Finished once the key goes back up
+			Some(key) => (held & 1 << key == 0).then_some(key),
+			None => {
+				let fresh = held & !wait.ignored;
+				let key = (fresh != 0).then(|| fresh.trailing_zeros() as u8);
+				if self.quirks.key_release {
+					wait.pressed = key;
+					None
+				} else {
+					key
+				}
 			}
-		}
-
-		if self.keypressed {
-			self.pc += 2;
+		};
+
+		self.keypressed = key.is_some();
+		match key {
+			Some(key) => {
+				self.v[self.get_vx() as usize] = key;
+				self.key_wait = None;
+				self.pc = self.pc.wrapping_add(2);
+			}
+			None => self.key_wait = Some(wait),
 		}
 	}
 
@@ -527,18 +808,50 @@ impl Cpu {
 // Super chip 1.0 instructions
 
 impl Cpu {
-	fn ins_0x00FD(&self) {
-		std::process::exit(1);
+	fn ins_0x00CN(&mut self) {
+		self.scroll(0, self.get_n() as isize);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0x00FB(&mut self) {
+		self.scroll(4, 0);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0x00FC(&mut self) {
+		self.scroll(-4, 0);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0x00FD(&mut self) {
+		self.halted = true;
 	}
 
 	fn ins_0x00FE(&mut self) {
-		self.set_mode(video_mode::CHIP8);
-		self.pc += 2;
+		self.set_mode(VideoMode::Lores);
+		self.pc = self.pc.wrapping_add(2);
 	}
 
 	fn ins_0x00FF(&mut self) {
-		self.set_mode(video_mode::SCHIP8);
-		self.pc += 2;
+		self.set_mode(VideoMode::Hires);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0xF030(&mut self) {
+		self.index = (BIG_FONT_START + (self.v[self.get_vx() as usize] & 0xF) as usize * 10) as u16;
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0xF075(&mut self) {
+		let vx = self.get_vx() as usize;
+		self.rpl[..=vx].copy_from_slice(&self.v[..=vx]);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0xF085(&mut self) {
+		let vx = self.get_vx() as usize;
+		self.v[..=vx].copy_from_slice(&self.rpl[..=vx]);
+		self.pc = self.pc.wrapping_add(2);
 	}
 }
 
@@ -547,23 +860,297 @@ impl Cpu {
 // http://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html
 
 impl Cpu {
+	fn ins_0x00DN(&mut self) {
+		self.scroll(0, -(self.get_n() as isize));
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	// 5XY2 and 5XY3 work on the range VX..VY in either direction and leave I untouched
 	fn ins_05XY2(&mut self){
+		let vx = self.get_vx() as usize;
+		let vy = self.get_vy() as usize;
+		for (offset, reg) in Self::register_range(vx, vy).enumerate() {
+			self.mem[self.index.wrapping_add(offset as u16) as usize] = self.v[reg];
+		}
+		self.record(AccessKind::Write, self.index, (vx.abs_diff(vy) + 1) as u16);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_05XY3(&mut self) {
+		let vx = self.get_vx() as usize;
+		let vy = self.get_vy() as usize;
+		for (offset, reg) in Self::register_range(vx, vy).enumerate() {
+			self.v[reg] = self.mem[self.index.wrapping_add(offset as u16) as usize];
+		}
+		self.record(AccessKind::Read, self.index, (vx.abs_diff(vy) + 1) as u16);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0xF000(&mut self) {
+		self.index = self.read_word(self.pc.wrapping_add(2));
+		self.pc = self.pc.wrapping_add(4);
+	}
+
+	fn ins_0xFN01(&mut self) {
+		self.planes = self.get_vx() as u8 & 0x3;
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0xF002(&mut self) {
+		for i in 0..16 {
+			self.audio_pattern[i] = self.mem[self.index.wrapping_add(i as u16) as usize];
+		}
+		self.record(AccessKind::Read, self.index, 16);
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	fn ins_0xF03A(&mut self) {
+		self.pitch = self.v[self.get_vx() as usize];
+		self.pc = self.pc.wrapping_add(2);
+	}
 
+	fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+		if x <= y {
+			Box::new(x..=y)
+		} else {
+			Box::new((y..=x).rev())
+		}
 	}
 }
 
 // Misc functions
 impl Cpu {
-	pub fn get_graphics(&self) -> [u8; 64 * 32] {
-		self.display
+	/// The visible part of the framebuffer, `width() * height()` pixels holding a bit per plane
+	pub fn get_graphics(&self) -> &[u8] {
+		&self.display[..self.width() * self.height()]
+	}
+
+	/// Writes the visible framebuffer to `out` as RGBA, 4 bytes per pixel row by row
+	pub fn render_rgba(&self, palette: &Palette, out: &mut [u8]) {
+		let pixels = self.get_graphics();
+		assert_eq!(out.len(), pixels.len() * 4, "Output needs 4 bytes for each of the {} pixels", pixels.len());
+		for (rgba, &pixel) in out.chunks_exact_mut(4).zip(pixels) {
+			rgba.copy_from_slice(&palette.color(pixel));
+		}
+	}
+
+	pub fn pc(&self) -> u16 {
+		self.pc
+	}
+
+	/// Moves execution to `pc`, a pending FX0A wait is abandoned
+	pub fn set_pc(&mut self, pc: u16) {
+		self.pc = pc;
+		self.key_wait = None;
+	}
+
+	/// Number of return addresses on the stack
+	pub fn sp(&self) -> u16 {
+		self.sp
+	}
+
+	/// The return addresses currently on the stack, oldest first
+	pub fn stack(&self) -> &[u16] {
+		&self.stack[..self.sp as usize]
+	}
+
+	/// Replaces a return address that is currently on the stack
+	pub fn set_stack_entry(&mut self, depth: usize, addr: u16) {
+		assert!(depth < self.sp as usize, "Stack entry {} is not in use", depth);
+		self.stack[depth] = addr;
+	}
+
+	pub fn index(&self) -> u16 {
+		self.index
+	}
+
+	pub fn set_index(&mut self, index: u16) {
+		self.index = index;
+	}
+
+	pub fn registers(&self) -> Registers {
+		Registers {
+			v: self.v,
+			index: self.index,
+			pc: self.pc,
+			sp: self.sp,
+			stack: self.stack,
+			dt: self.dt,
+			st: self.st,
+		}
+	}
+
+	/// Restores registers taken with [`Cpu::registers`], memory and the display are left alone
+	pub fn set_registers(&mut self, registers: &Registers) {
+		self.v = registers.v;
+		self.index = registers.index;
+		self.pc = registers.pc;
+		self.sp = registers.sp.min(self.stack.len() as u16);
+		self.stack = registers.stack;
+		self.dt = registers.dt;
+		self.st = registers.st;
+		self.key_wait = None;
+	}
+
+	/// XO-Chip planes selected by FN01
+	pub fn planes(&self) -> u8 {
+		self.planes
+	}
+
+	/// Memory reads and writes made by the last executed instruction
+	pub fn last_accesses(&self) -> &[MemAccess] {
+		&self.accesses
+	}
+
+	pub fn video_mode(&self) -> VideoMode {
+		self.vmode
+	}
+
+	pub fn width(&self) -> usize {
+		match self.vmode {
+			VideoMode::Lores => SCREEN_WIDTH,
+			VideoMode::Hires => HIRES_WIDTH,
+		}
+	}
+
+	pub fn height(&self) -> usize {
+		match self.vmode {
+			VideoMode::Lores => SCREEN_HEIGHT,
+			VideoMode::Hires => HIRES_HEIGHT,
+		}
+	}
+
+	/// True once the program executed 00FD
+	pub fn halted(&self) -> bool {
+		self.halted
+	}
+
+	/// Set when the program did something that can't be executed, [`Cpu::cycle`] does nothing
+	/// until a reset
+	pub fn fault(&self) -> Option<Fault> {
+		self.fault
+	}
+
+	/// The XO-Chip audio pattern buffer loaded by F002
+	pub fn audio_pattern(&self) -> &[u8; 16] {
+		&self.audio_pattern
+	}
+
+	/// The XO-Chip playback pitch set by FX3A
+	pub fn pitch(&self) -> u8 {
+		self.pitch
+	}
+
+	/// Decrements the delay and sound timers, frontends call this at 60hz
+	pub fn tick_timers(&mut self) {
+		self.dt = self.dt.saturating_sub(1);
+		self.st = self.st.saturating_sub(1);
 	}
 
 	pub fn load_byte_to_memory(&mut self, v: u8, pos: usize) {
 		self.mem[pos] = v;
 	}
 
-	fn set_mode(&mut self, v: video_mode) {
+	pub fn memory(&self) -> &[u8; MEMSIZE] {
+		&self.mem
+	}
+
+	/// Overwrites memory from address 0 with `data`, anything past the end of memory is ignored
+	pub fn load_memory(&mut self, data: &[u8]) {
+		let len = data.len().min(MEMSIZE);
+		self.mem[..len].copy_from_slice(&data[..len]);
+	}
+
+	/// Size of the loaded rom, it occupies `PROGRAM_START..PROGRAM_START + rom_len()`
+	pub fn rom_len(&self) -> usize {
+		self.rom.len()
+	}
+
+	/// Attaches a tracer that records every following instruction, returns the previous one
+	pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+		std::mem::replace(&mut self.tracer, tracer)
+	}
+
+	pub fn tracing(&self) -> bool {
+		self.tracer.is_some()
+	}
+
+	/// Attaches a profiler that counts every following instruction, returns the previous one
+	pub fn set_profiler(&mut self, profiler: Option<Profiler>) -> Option<Profiler> {
+		std::mem::replace(&mut self.profiler, profiler)
+	}
+
+	pub fn profiler(&self) -> Option<&Profiler> {
+		self.profiler.as_ref()
+	}
+
+	pub fn quirks(&self) -> Quirks {
+		self.quirks
+	}
+
+	pub fn set_quirks(&mut self, quirks: Quirks) {
+		self.quirks = quirks;
+	}
+
+	/// True while FX0A at pc is blocked waiting for a key
+	pub fn waiting_for_key(&self) -> bool {
+		self.key_wait.is_some()
+	}
+
+	/// Which bytes of memory were executed, read or written so far
+	pub fn coverage(&self) -> &Coverage {
+		&self.coverage
+	}
+
+	pub fn clear_coverage(&mut self) {
+		self.coverage.clear();
+	}
+
+	fn trace_entry(&self) -> TraceEntry {
+		TraceEntry {
+			pc: self.pc,
+			opcode: self.opcode,
+			next: self.read_word(self.pc.wrapping_add(2)),
+			v: self.v,
+			index: self.index,
+			dt: self.dt,
+			st: self.st,
+		}
+	}
+
+	fn record(&mut self, kind: AccessKind, addr: u16, len: u16) {
+		self.accesses.push(MemAccess { kind, addr, len });
+		let flag = match kind {
+			AccessKind::Read => coverage::READ,
+			AccessKind::Write => coverage::WRITTEN,
+		};
+		self.coverage.mark(addr, len, flag);
+	}
+
+	fn set_mode(&mut self, v: VideoMode) {
+		// Switching resolution clears the screen
 		self.vmode = v;
+		self.display = [0; HIRES_WIDTH * HIRES_HEIGHT];
+		self.draw = true;
+	}
+
+	// Moves the selected planes by dx, dy pixels, anything scrolled in from outside is blank
+	fn scroll(&mut self, dx: isize, dy: isize) {
+		let (width, height) = (self.width() as isize, self.height() as isize);
+		let old = self.display;
+		for y in 0..height {
+			for x in 0..width {
+				let (sx, sy) = (x - dx, y - dy);
+				let moved = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+					old[(sx + sy * width) as usize] & self.planes
+				} else {
+					0
+				};
+				let idx = (x + y * width) as usize;
+				self.display[idx] = (old[idx] & !self.planes) | moved;
+			}
+		}
+		self.draw = true;
 	}
 }
 
@@ -573,4 +1160,738 @@ impl Cpu {
 		self.keypad[key as usize] = pressed;
 
 	}
-}
\ No newline at end of file
+}
+
+// Builds a cpu with the given instructions loaded at the program start, shared by the tests of
+// every module
+#[cfg(test)]
+pub(crate) fn cpu_with(program: &[u16]) -> Cpu {
+	let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+	let mut cpu = Cpu::init();
+	cpu.load(&rom).unwrap();
+	cpu
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Runs a single instruction
+	fn exec(program: &[u16], setup: impl FnOnce(&mut Cpu)) -> Cpu {
+		let mut cpu = cpu_with(program);
+		setup(&mut cpu);
+		cpu.cycle();
+		cpu
+	}
+
+	const START: u16 = PROGRAM_START as u16;
+
+	fn pixel(cpu: &Cpu, x: usize, y: usize) -> u8 {
+		cpu.display[x + y * cpu.width()]
+	}
+
+	#[test]
+	fn load_places_rom_and_fonts() {
+		let cpu = cpu_with(&[0x1234]);
+		assert_eq!(cpu.mem[PROGRAM_START], 0x12);
+		assert_eq!(cpu.mem[PROGRAM_START + 1], 0x34);
+		assert_eq!(cpu.mem[FONT_START..FONT_START + 80], FONTS);
+		assert_eq!(cpu.mem[BIG_FONT_START..BIG_FONT_START + 160], BIG_FONTS);
+		assert_eq!(cpu.pc, START);
+	}
+
+	#[test]
+	fn load_memory_replaces_ram() {
+		let mut cpu = cpu_with(&[0x1234]);
+		assert_eq!(cpu.rom_len(), 2);
+		cpu.load_memory(&[0xAA; 0x201]);
+		assert_eq!(cpu.memory()[0x200], 0xAA);
+		assert_eq!(cpu.memory()[0x201], 0x34);
+
+		// Oversized images are cut off at the end of memory
+		cpu.load_memory(&vec![0xBB; MEMSIZE + 10]);
+		assert_eq!(cpu.memory()[MEMSIZE - 1], 0xBB);
+	}
+
+	#[test]
+	fn op_00e0_clears_screen() {
+		let cpu = exec(&[0x00E0], |c| c.display[10] = 1);
+		assert!(cpu.get_graphics().iter().all(|&p| p == 0));
+		assert!(cpu.draw);
+		assert_eq!(cpu.pc, START + 2);
+	}
+
+	#[test]
+	fn op_00e0_only_clears_selected_planes() {
+		let cpu = exec(&[0x00E0], |c| {
+			c.display[0] = 0b11;
+			c.planes = 0b10;
+		});
+		assert_eq!(cpu.display[0], 0b01);
+	}
+
+	#[test]
+	fn op_2nnn_and_00ee_call_and_return() {
+		let mut cpu = cpu_with(&[0x2300]);
+		cpu.load_byte_to_memory(0x00, 0x300);
+		cpu.load_byte_to_memory(0xEE, 0x301);
+		cpu.cycle();
+		assert_eq!(cpu.pc, 0x300);
+		assert_eq!(cpu.sp, 1);
+		assert_eq!(cpu.stack[0], START);
+		cpu.cycle();
+		assert_eq!(cpu.pc, START + 2);
+		assert_eq!(cpu.sp, 0);
+	}
+
+	#[test]
+	fn stack_accessors_only_expose_live_entries() {
+		let mut cpu = cpu_with(&[0x2300]);
+		assert!(cpu.stack().is_empty());
+		cpu.cycle();
+		assert_eq!(cpu.stack(), [START]);
+		cpu.set_stack_entry(0, 0x400);
+		assert_eq!(cpu.stack(), [0x400]);
+	}
+
+	#[test]
+	fn registers_carry_over_to_another_cpu() {
+		let mut cpu = exec(&[0x2300], |c| c.v[5] = 7);
+		cpu.set_index(0x345);
+		let mut other = Cpu::init();
+		other.set_registers(&cpu.registers());
+		assert_eq!(other.v[5], 7);
+		assert_eq!((other.pc(), other.index()), (0x300, 0x345));
+		assert_eq!(other.stack(), [START]);
+	}
+
+	// LD V0, 5 / LD I, 0x200 / LD [I], V0 overwrites the first instruction
+	const SELF_MODIFYING: [u16; 3] = [0x6005, 0xA200, 0xF055];
+
+	#[test]
+	fn load_rejects_roms_larger_than_memory() {
+		let mut cpu = Cpu::init();
+		assert_eq!(cpu.load(&vec![0; MAX_ROM_LEN + 1]), Err(RomTooLarge { len: MAX_ROM_LEN + 1 }));
+		assert_eq!(cpu.rom_len(), 0);
+		assert!(cpu.load(&vec![0; MAX_ROM_LEN]).is_ok());
+	}
+
+	#[test]
+	fn soft_reset_keeps_memory() {
+		let mut cpu = cpu_with(&SELF_MODIFYING);
+		for _ in 0..3 {
+			cpu.cycle();
+		}
+		cpu.soft_reset();
+		assert_eq!((cpu.pc(), cpu.index(), cpu.v[0]), (START, 0, 0));
+		assert_eq!(cpu.read_word(START), 0x0505);
+	}
+
+	#[test]
+	fn hard_reset_reloads_the_rom() {
+		let mut cpu = cpu_with(&SELF_MODIFYING);
+		for _ in 0..3 {
+			cpu.cycle();
+		}
+		cpu.hard_reset();
+		assert_eq!((cpu.pc(), cpu.v[0]), (START, 0));
+		assert_eq!(cpu.read_word(START), 0x6005);
+		assert_eq!(cpu.rom_len(), 6);
+		assert_eq!(cpu.memory()[FONT_START], FONTS[0]);
+	}
+
+	#[test]
+	fn op_1nnn_jumps() {
+		let cpu = exec(&[0x1ABC], |_| {});
+		assert_eq!(cpu.pc, 0xABC);
+	}
+
+	#[test]
+	fn op_3xnn_skips_when_equal() {
+		assert_eq!(exec(&[0x3312], |c| c.v[3] = 0x12).pc, START + 4);
+		assert_eq!(exec(&[0x3312], |c| c.v[3] = 0x13).pc, START + 2);
+	}
+
+	#[test]
+	fn op_4xnn_skips_when_not_equal() {
+		assert_eq!(exec(&[0x4312], |c| c.v[3] = 0x12).pc, START + 2);
+		assert_eq!(exec(&[0x4312], |c| c.v[3] = 0x13).pc, START + 4);
+	}
+
+	#[test]
+	fn op_5xy0_skips_when_registers_equal() {
+		assert_eq!(exec(&[0x5120], |c| { c.v[1] = 7; c.v[2] = 7; }).pc, START + 4);
+		assert_eq!(exec(&[0x5120], |c| { c.v[1] = 7; c.v[2] = 8; }).pc, START + 2);
+	}
+
+	#[test]
+	fn skips_jump_over_long_f000_instruction() {
+		let cpu = exec(&[0x3000, 0xF000, 0x1234], |_| {});
+		assert_eq!(cpu.pc, START + 6);
+	}
+
+	#[test]
+	fn op_6xnn_loads() {
+		let cpu = exec(&[0x6A42], |_| {});
+		assert_eq!(cpu.v[0xA], 0x42);
+		assert_eq!(cpu.pc, START + 2);
+	}
+
+	#[test]
+	fn op_7xnn_adds_without_touching_vf() {
+		let cpu = exec(&[0x7105], |c| { c.v[1] = 0xFE; c.v[0xF] = 9; });
+		assert_eq!(cpu.v[1], 0x03);
+		assert_eq!(cpu.v[0xF], 9);
+	}
+
+	#[test]
+	fn op_8xy0_to_8xy3_logic() {
+		let setup = |c: &mut Cpu| { c.v[1] = 0b1100; c.v[2] = 0b1010; };
+		assert_eq!(exec(&[0x8120], setup).v[1], 0b1010);
+		assert_eq!(exec(&[0x8121], setup).v[1], 0b1110);
+		assert_eq!(exec(&[0x8122], setup).v[1], 0b1000);
+		assert_eq!(exec(&[0x8123], setup).v[1], 0b0110);
+	}
+
+	#[test]
+	fn op_8xy4_add_with_carry() {
+		let cpu = exec(&[0x8124], |c| { c.v[1] = 0xFF; c.v[2] = 0x02; });
+		assert_eq!(cpu.v[1], 0x01);
+		assert_eq!(cpu.v[0xF], 1);
+
+		let cpu = exec(&[0x8124], |c| { c.v[1] = 0x10; c.v[2] = 0x02; c.v[0xF] = 1; });
+		assert_eq!(cpu.v[1], 0x12);
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_8xy4_vf_as_destination_keeps_flag() {
+		let cpu = exec(&[0x8F14], |c| { c.v[0xF] = 0xFF; c.v[1] = 0x01; });
+		assert_eq!(cpu.v[0xF], 1);
+	}
+
+	#[test]
+	fn op_8xy5_sub_sets_flag_when_no_borrow() {
+		let cpu = exec(&[0x8125], |c| { c.v[1] = 5; c.v[2] = 3; });
+		assert_eq!(cpu.v[1], 2);
+		assert_eq!(cpu.v[0xF], 1);
+
+		// Equal operands do not borrow
+		let cpu = exec(&[0x8125], |c| { c.v[1] = 5; c.v[2] = 5; });
+		assert_eq!(cpu.v[1], 0);
+		assert_eq!(cpu.v[0xF], 1);
+
+		let cpu = exec(&[0x8125], |c| { c.v[1] = 3; c.v[2] = 5; });
+		assert_eq!(cpu.v[1], 0xFE);
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_8xy5_vf_as_destination_keeps_flag() {
+		let cpu = exec(&[0x8F15], |c| { c.v[0xF] = 1; c.v[1] = 2; });
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_8xy6_shift_right() {
+		let cpu = exec(&[0x8126], |c| c.v[1] = 0b101);
+		assert_eq!(cpu.v[1], 0b10);
+		assert_eq!(cpu.v[0xF], 1);
+
+		let cpu = exec(&[0x8F06], |c| c.v[0xF] = 0b10);
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_8xy7_reverse_sub() {
+		let cpu = exec(&[0x8127], |c| { c.v[1] = 3; c.v[2] = 5; });
+		assert_eq!(cpu.v[1], 2);
+		assert_eq!(cpu.v[0xF], 1);
+
+		let cpu = exec(&[0x8127], |c| { c.v[1] = 5; c.v[2] = 5; });
+		assert_eq!(cpu.v[1], 0);
+		assert_eq!(cpu.v[0xF], 1);
+
+		let cpu = exec(&[0x8127], |c| { c.v[1] = 5; c.v[2] = 3; });
+		assert_eq!(cpu.v[1], 0xFE);
+		assert_eq!(cpu.v[0xF], 0);
+
+		let cpu = exec(&[0x8F17], |c| { c.v[0xF] = 5; c.v[1] = 3; });
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_8xye_shift_left() {
+		let cpu = exec(&[0x812E], |c| c.v[1] = 0b1000_0001);
+		assert_eq!(cpu.v[1], 0b10);
+		assert_eq!(cpu.v[0xF], 1);
+
+		let cpu = exec(&[0x8F0E], |c| c.v[0xF] = 0b0100_0000);
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_9xy0_skips_when_registers_differ() {
+		assert_eq!(exec(&[0x9120], |c| { c.v[1] = 7; c.v[2] = 8; }).pc, START + 4);
+		assert_eq!(exec(&[0x9120], |c| { c.v[1] = 7; c.v[2] = 7; }).pc, START + 2);
+	}
+
+	#[test]
+	fn op_annn_sets_index() {
+		assert_eq!(exec(&[0xA123], |_| {}).index, 0x123);
+	}
+
+	#[test]
+	fn op_bnnn_jumps_with_v0_offset() {
+		assert_eq!(exec(&[0xB300], |c| c.v[0] = 0x10).pc, 0x310);
+	}
+
+	#[test]
+	fn op_cxnn_masks_random_value() {
+		let cpu = exec(&[0xC100], |c| c.v[1] = 0xFF);
+		assert_eq!(cpu.v[1], 0);
+
+		let cpu = exec(&[0xC10F], |_| {});
+		assert_eq!(cpu.v[1] & 0xF0, 0);
+	}
+
+	#[test]
+	fn op_dxyn_draws_and_detects_collision() {
+		let mut cpu = cpu_with(&[0xD015, 0xD015]);
+		cpu.index = 0;
+		cpu.cycle();
+		assert_eq!(cpu.v[0xF], 0);
+		assert!(cpu.draw);
+		// Top row of the "0" glyph is 0xF0
+		assert_eq!(pixel(&cpu, 0, 0), 1);
+		assert_eq!(pixel(&cpu, 3, 0), 1);
+		assert_eq!(pixel(&cpu, 4, 0), 0);
+		assert_eq!(pixel(&cpu, 1, 1), 0);
+
+		cpu.cycle();
+		assert_eq!(cpu.v[0xF], 1);
+		assert!(cpu.get_graphics().iter().all(|&p| p == 0));
+	}
+
+	#[test]
+	fn op_dxyn_wraps_around_edges() {
+		let cpu = exec(&[0xD121], |c| {
+			c.index = 0;
+			c.v[1] = 62;
+			c.v[2] = 31;
+		});
+		assert_eq!(pixel(&cpu, 62, 31), 1);
+		assert_eq!(pixel(&cpu, 63, 31), 1);
+		assert_eq!(pixel(&cpu, 0, 31), 1);
+		assert_eq!(pixel(&cpu, 1, 31), 1);
+		assert_eq!(pixel(&cpu, 2, 31), 0);
+	}
+
+	#[test]
+	fn op_dxyn_start_position_wraps() {
+		let cpu = exec(&[0xD121], |c| {
+			c.index = 0;
+			c.v[1] = 64 + 2;
+			c.v[2] = 32 + 1;
+		});
+		assert_eq!(pixel(&cpu, 2, 1), 1);
+	}
+
+	#[test]
+	fn op_dxyn_vf_as_coordinate_is_read_before_flag() {
+		let cpu = exec(&[0xDF01], |c| {
+			c.index = 0;
+			c.v[0xF] = 5;
+		});
+		assert_eq!(pixel(&cpu, 5, 0), 1);
+		assert_eq!(cpu.v[0xF], 0);
+	}
+
+	#[test]
+	fn op_dxy0_draws_16x16_sprite() {
+		let mut cpu = cpu_with(&[0x00FF, 0xD010]);
+		cpu.index = 0x300;
+		for i in 0..32 {
+			cpu.mem[0x300 + i] = 0xFF;
+		}
+		cpu.cycle();
+		cpu.cycle();
+		assert_eq!(pixel(&cpu, 15, 15), 1);
+		assert_eq!(pixel(&cpu, 16, 0), 0);
+		assert_eq!(pixel(&cpu, 0, 16), 0);
+	}
+
+	#[test]
+	fn op_dxyn_draws_each_selected_plane() {
+		let cpu = exec(&[0xD011], |c| {
+			c.planes = 0b11;
+			c.index = 0x300;
+			c.mem[0x300] = 0x80;
+			c.mem[0x301] = 0x40;
+		});
+		assert_eq!(pixel(&cpu, 0, 0), 0b01);
+		assert_eq!(pixel(&cpu, 1, 0), 0b10);
+	}
+
+	#[test]
+	fn render_rgba_uses_plane_colours() {
+		let mut cpu = Cpu::init();
+		cpu.display[0] = 0b01;
+		cpu.display[1] = 0b11;
+		let mut out = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+		cpu.render_rgba(&Palette::CLASSIC, &mut out);
+		assert_eq!(out[0..4], Palette::CLASSIC.colors[1]);
+		assert_eq!(out[4..8], Palette::CLASSIC.colors[3]);
+		assert_eq!(out[8..12], Palette::CLASSIC.colors[0]);
+	}
+
+	#[test]
+	fn op_ex9e_and_exa1_check_keys() {
+		assert_eq!(exec(&[0xE19E], |c| { c.v[1] = 0xA; c.set_key(0xA, 1); }).pc, START + 4);
+		assert_eq!(exec(&[0xE19E], |c| c.v[1] = 0xA).pc, START + 2);
+		assert_eq!(exec(&[0xE1A1], |c| c.v[1] = 0xA).pc, START + 4);
+		assert_eq!(exec(&[0xE1A1], |c| { c.v[1] = 0xA; c.set_key(0xA, 1); }).pc, START + 2);
+	}
+
+	#[test]
+	fn op_fx07_and_fx15_delay_timer() {
+		assert_eq!(exec(&[0xF315], |c| c.v[3] = 42).dt, 42);
+		assert_eq!(exec(&[0xF307], |c| c.dt = 17).v[3], 17);
+	}
+
+	#[test]
+	fn tick_timers_counts_down_to_zero() {
+		let mut cpu = Cpu::init();
+		cpu.dt = 2;
+		cpu.st = 1;
+		cpu.tick_timers();
+		cpu.tick_timers();
+		cpu.tick_timers();
+		assert_eq!((cpu.dt, cpu.st), (0, 0));
+	}
+
+	#[test]
+	fn op_fx18_sound_timer() {
+		assert_eq!(exec(&[0xF318], |c| c.v[3] = 42).st, 42);
+	}
+
+	#[test]
+	fn op_fx0a_waits_for_press_and_release() {
+		let mut cpu = cpu_with(&[0xF20A]);
+		cpu.cycle();
+		assert_eq!(cpu.pc, START);
+		assert!(cpu.waiting_for_key());
+
+		cpu.set_key(0x7, 1);
+		cpu.cycle();
+		assert_eq!(cpu.pc, START);
+
+		cpu.set_key(0x7, 0);
+		cpu.cycle();
+		assert_eq!(cpu.v[2], 0x7);
+		assert_eq!(cpu.pc, START + 2);
+		assert!(!cpu.waiting_for_key());
+	}
+
+	#[test]
+	fn op_fx0a_wait_is_abandoned_when_pc_moves() {
+		let mut cpu = cpu_with(&[0xF20A, 0xF30A]);
+		cpu.cycle();
+		cpu.set_key(0x7, 1);
+		cpu.cycle();
+		cpu.set_pc(START + 2);
+		assert!(!cpu.waiting_for_key());
+
+		// The release belongs to the abandoned wait, the new FX0A keeps waiting
+		cpu.set_key(0x7, 0);
+		cpu.cycle();
+		assert_eq!(cpu.pc, START + 2);
+		assert_eq!(cpu.v[3], 0);
+		assert!(cpu.waiting_for_key());
+	}
+
+	#[test]
+	fn op_fx0a_ignores_keys_held_before_the_wait() {
+		let mut cpu = cpu_with(&[0xF20A]);
+		cpu.set_key(0x3, 1);
+		cpu.cycle();
+		cpu.set_key(0x3, 0);
+		cpu.cycle();
+		assert_eq!(cpu.pc, START);
+
+		// Pressed again, it counts
+		cpu.set_key(0x3, 1);
+		cpu.cycle();
+		cpu.set_key(0x3, 0);
+		cpu.cycle();
+		assert_eq!(cpu.v[2], 0x3);
+		assert_eq!(cpu.pc, START + 2);
+	}
+
+	#[test]
+	fn op_fx0a_press_only_quirk() {
+		let mut cpu = cpu_with(&[0xF20A]);
+		cpu.set_quirks(Quirks { key_release: false });
+		cpu.cycle();
+		assert_eq!(cpu.pc, START);
+
+		cpu.set_key(0x7, 1);
+		cpu.cycle();
+		assert_eq!(cpu.v[2], 0x7);
+		assert_eq!(cpu.pc, START + 2);
+	}
+
+	#[test]
+	fn op_fx1e_adds_to_index() {
+		let cpu = exec(&[0xF21E], |c| { c.index = 0x100; c.v[2] = 0x20; c.v[0xF] = 3; });
+		assert_eq!(cpu.index, 0x120);
+		assert_eq!(cpu.v[0xF], 3);
+	}
+
+	#[test]
+	fn op_fx29_points_to_small_font() {
+		assert_eq!(exec(&[0xF229], |c| c.v[2] = 0xA).index, FONT_START as u16 + 50);
+		// Only the low nibble selects a glyph
+		assert_eq!(exec(&[0xF229], |c| c.v[2] = 0xFA).index, FONT_START as u16 + 50);
+	}
+
+	#[test]
+	fn op_fx30_points_to_big_font() {
+		assert_eq!(exec(&[0xF230], |c| c.v[2] = 3).index, BIG_FONT_START as u16 + 30);
+	}
+
+	#[test]
+	fn op_fx33_stores_bcd() {
+		let cpu = exec(&[0xF233], |c| { c.index = 0x300; c.v[2] = 254; });
+		assert_eq!(cpu.mem[0x300..0x303], [2, 5, 4]);
+		assert_eq!(cpu.index, 0x300);
+	}
+
+	#[test]
+	fn op_fx55_and_fx65_store_and_load() {
+		let cpu = exec(&[0xF255], |c| {
+			c.index = 0x300;
+			c.v[0] = 1;
+			c.v[1] = 2;
+			c.v[2] = 3;
+			c.v[3] = 4;
+		});
+		assert_eq!(cpu.mem[0x300..0x304], [1, 2, 3, 0]);
+		assert_eq!(cpu.index, 0x300);
+
+		let cpu = exec(&[0xF165], |c| {
+			c.index = 0x300;
+			c.mem[0x300] = 9;
+			c.mem[0x301] = 8;
+			c.mem[0x302] = 7;
+		});
+		assert_eq!(cpu.v[..3], [9, 8, 0]);
+		assert_eq!(cpu.index, 0x300);
+	}
+
+	#[test]
+	fn op_fx75_and_fx85_rpl_flags() {
+		let mut cpu = cpu_with(&[0xF275, 0xF285]);
+		cpu.v[..3].copy_from_slice(&[1, 2, 3]);
+		cpu.cycle();
+		assert_eq!(cpu.rpl[..3], [1, 2, 3]);
+
+		cpu.v = [0; 16];
+		cpu.cycle();
+		assert_eq!(cpu.v[..3], [1, 2, 3]);
+	}
+
+	#[test]
+	fn op_00fd_halts() {
+		let mut cpu = cpu_with(&[0x00FD]);
+		cpu.cycle();
+		assert!(cpu.halted());
+		cpu.cycle();
+		assert_eq!(cpu.pc, START);
+	}
+
+	#[test]
+	fn unknown_opcode_faults() {
+		// BNNN past the end of the rom lands on zeroed memory
+		let mut cpu = cpu_with(&[0xB300]);
+		cpu.cycle();
+		cpu.cycle();
+		assert_eq!(cpu.fault(), Some(Fault::InvalidOpcode { pc: 0x300, opcode: 0x0000 }));
+		cpu.cycle();
+		assert_eq!(cpu.pc, 0x300);
+
+		for opcode in [0x5001, 0x8008, 0xE000, 0xF0FF] {
+			assert_eq!(exec(&[opcode], |_| {}).fault(), Some(Fault::InvalidOpcode { pc: START, opcode }));
+		}
+	}
+
+	#[test]
+	fn op_00ee_on_empty_stack_faults() {
+		let cpu = exec(&[0x00EE], |_| {});
+		assert_eq!(cpu.fault(), Some(Fault::StackUnderflow { pc: START }));
+		assert_eq!((cpu.pc, cpu.sp), (START, 0));
+	}
+
+	#[test]
+	fn op_2nnn_past_16_calls_faults() {
+		// Calls itself forever
+		let mut cpu = cpu_with(&[0x2200]);
+		for _ in 0..16 {
+			cpu.cycle();
+		}
+		assert_eq!((cpu.sp, cpu.fault()), (16, None));
+		cpu.cycle();
+		assert_eq!(cpu.fault(), Some(Fault::StackOverflow { pc: START }));
+		assert_eq!(cpu.sp, 16);
+	}
+
+	#[test]
+	fn pc_wraps_at_the_end_of_memory() {
+		let mut cpu = cpu_with(&[]);
+		cpu.load_byte_to_memory(0x60, 0xFFFE);
+		cpu.load_byte_to_memory(0x05, 0xFFFF);
+		cpu.set_pc(0xFFFE);
+		cpu.cycle();
+		assert_eq!((cpu.pc, cpu.v[0]), (0, 5));
+	}
+
+	#[test]
+	fn soft_reset_clears_a_fault() {
+		let mut cpu = cpu_with(&[0x00EE]);
+		cpu.cycle();
+		assert!(cpu.fault().is_some());
+		cpu.soft_reset();
+		assert_eq!(cpu.fault(), None);
+	}
+
+	#[test]
+	fn op_00fe_and_00ff_switch_resolution() {
+		let mut cpu = cpu_with(&[0x00FF, 0x00FE]);
+		cpu.display[0] = 1;
+		cpu.cycle();
+		assert_eq!(cpu.vmode, VideoMode::Hires);
+		assert_eq!((cpu.width(), cpu.height()), (128, 64));
+		assert_eq!(cpu.display[0], 0);
+
+		cpu.cycle();
+		assert_eq!(cpu.vmode, VideoMode::Lores);
+		assert_eq!(cpu.get_graphics().len(), 64 * 32);
+	}
+
+	#[test]
+	fn op_00cn_scrolls_down() {
+		let cpu = exec(&[0x00C2], |c| c.display[3] = 1);
+		assert_eq!(pixel(&cpu, 3, 0), 0);
+		assert_eq!(pixel(&cpu, 3, 2), 1);
+	}
+
+	#[test]
+	fn op_00dn_scrolls_up() {
+		let cpu = exec(&[0x00D2], |c| c.display[3 + 2 * 64] = 1);
+		assert_eq!(pixel(&cpu, 3, 2), 0);
+		assert_eq!(pixel(&cpu, 3, 0), 1);
+	}
+
+	#[test]
+	fn op_00fb_and_00fc_scroll_sideways() {
+		let cpu = exec(&[0x00FB], |c| c.display[10] = 1);
+		assert_eq!(pixel(&cpu, 14, 0), 1);
+		assert_eq!(pixel(&cpu, 10, 0), 0);
+
+		let cpu = exec(&[0x00FC], |c| c.display[10] = 1);
+		assert_eq!(pixel(&cpu, 6, 0), 1);
+		assert_eq!(pixel(&cpu, 10, 0), 0);
+
+		// Pixels pushed off the edge are lost
+		let cpu = exec(&[0x00FC], |c| c.display[1] = 1);
+		assert!(cpu.get_graphics().iter().all(|&p| p == 0));
+	}
+
+	#[test]
+	fn scroll_only_moves_selected_planes() {
+		let cpu = exec(&[0x00C1], |c| {
+			c.planes = 0b10;
+			c.display[0] = 0b11;
+		});
+		assert_eq!(pixel(&cpu, 0, 0), 0b01);
+		assert_eq!(pixel(&cpu, 0, 1), 0b10);
+	}
+
+	#[test]
+	fn op_5xy2_and_5xy3_save_and_load_ranges() {
+		let cpu = exec(&[0x5132], |c| {
+			c.index = 0x300;
+			c.v[1..4].copy_from_slice(&[1, 2, 3]);
+		});
+		assert_eq!(cpu.mem[0x300..0x303], [1, 2, 3]);
+		assert_eq!(cpu.index, 0x300);
+
+		// Reversed ranges go downwards through the registers
+		let cpu = exec(&[0x5312], |c| {
+			c.index = 0x300;
+			c.v[1..4].copy_from_slice(&[1, 2, 3]);
+		});
+		assert_eq!(cpu.mem[0x300..0x303], [3, 2, 1]);
+
+		let cpu = exec(&[0x5133], |c| {
+			c.index = 0x300;
+			c.mem[0x300..0x303].copy_from_slice(&[7, 8, 9]);
+		});
+		assert_eq!(cpu.v[1..4], [7, 8, 9]);
+		assert_eq!(cpu.index, 0x300);
+	}
+
+	#[test]
+	fn op_f000_loads_long_index() {
+		let cpu = exec(&[0xF000, 0xBEEF], |_| {});
+		assert_eq!(cpu.index, 0xBEEF);
+		assert_eq!(cpu.pc, START + 4);
+	}
+
+	#[test]
+	fn op_fn01_selects_planes() {
+		assert_eq!(exec(&[0xF201], |_| {}).planes, 0b10);
+		assert_eq!(exec(&[0xF301], |_| {}).planes, 0b11);
+	}
+
+	#[test]
+	fn op_f002_loads_audio_pattern() {
+		let cpu = exec(&[0xF002], |c| {
+			c.index = 0x300;
+			for i in 0..16 {
+				c.mem[0x300 + i] = i as u8;
+			}
+		});
+		assert_eq!(cpu.audio_pattern[15], 15);
+		assert_eq!(cpu.pc, START + 2);
+	}
+
+	#[test]
+	fn memory_accesses_are_recorded() {
+		let cpu = exec(&[0xF255], |c| c.index = 0x300);
+		assert_eq!(cpu.last_accesses(), [MemAccess { kind: AccessKind::Write, addr: 0x300, len: 3 }]);
+
+		let cpu = exec(&[0xF233], |c| c.index = 0x300);
+		assert_eq!(cpu.last_accesses(), [MemAccess { kind: AccessKind::Write, addr: 0x300, len: 3 }]);
+
+		let cpu = exec(&[0xF165], |c| c.index = 0x300);
+		assert_eq!(cpu.last_accesses(), [MemAccess { kind: AccessKind::Read, addr: 0x300, len: 2 }]);
+
+		// Both planes read their own sprite
+		let cpu = exec(&[0xD014], |c| { c.index = 0x300; c.planes = 0b11; });
+		assert_eq!(cpu.last_accesses(), [MemAccess { kind: AccessKind::Read, addr: 0x300, len: 8 }]);
+
+		let cpu = exec(&[0x5312], |c| c.index = 0x300);
+		assert_eq!(cpu.last_accesses(), [MemAccess { kind: AccessKind::Write, addr: 0x300, len: 3 }]);
+
+		let mut cpu = cpu_with(&[0xF255, 0x6000]);
+		cpu.cycle();
+		cpu.cycle();
+		assert!(cpu.last_accesses().is_empty());
+	}
+
+	#[test]
+	fn op_fx3a_sets_pitch() {
+		assert_eq!(exec(&[0xF43A], |c| c.v[4] = 112).pitch, 112);
+	}
+}
@@ -0,0 +1,561 @@
+// Frontend independent debugger around a Cpu
+//
+// The debugger decides how many instructions a frontend is allowed to run. Frontends call
+// `run` every update with the number of instructions they would like to execute, the debugger
+// stops early when a breakpoint or watchpoint is hit or a step command finishes and then stays
+// paused.
+
+use std::collections::BTreeSet;
+
+use crate::cpu::{AccessKind, Cpu, Fault};
+
+/// Why the debugger paused execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+	/// The cpu is about to execute the instruction at this breakpoint
+	Breakpoint(u16),
+	/// A step, step over, step out or run to cursor command finished
+	Step,
+	/// The program executed 00FD
+	Halted,
+	/// The cpu hit something it can't execute and stopped
+	Fault(Fault),
+	/// An instruction touched a watched memory range or register
+	Watchpoint(WatchHit),
+}
+
+/// Which memory accesses a memory watchpoint breaks on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAccess {
+	Read,
+	Write,
+	ReadWrite,
+}
+
+impl WatchAccess {
+	fn matches(self, kind: AccessKind) -> bool {
+		matches!(
+			(self, kind),
+			(WatchAccess::ReadWrite, _) | (WatchAccess::Read, AccessKind::Read) | (WatchAccess::Write, AccessKind::Write)
+		)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+	/// Breaks when F033, F055, F065, DXYN or the XO-Chip loads and stores access `start..=end`
+	Memory { start: u16, end: u16, access: WatchAccess },
+	/// Breaks when an instruction changes VX
+	Register(u8),
+	/// Breaks when an instruction changes I
+	Index,
+	/// Breaks when an instruction changes the delay timer, timer ticks do not count
+	DelayTimer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+	Read(u16),
+	Write(u16),
+	Changed { old: u16, new: u16 },
+}
+
+/// A triggered watchpoint and the instruction that triggered it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+	pub watchpoint: Watchpoint,
+	pub pc: u16,
+	pub opcode: u16,
+	pub event: WatchEvent,
+}
+
+// The watched state from before an instruction executed
+struct Snapshot {
+	pc: u16,
+	opcode: u16,
+	v: [u8; 16],
+	index: u16,
+	dt: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+	Paused,
+	Running,
+	// Run until the CALL returns to the next instruction
+	StepOver { return_to: u16, sp: u16 },
+	// Run until a 00EE drops the stack below this depth
+	StepOut { sp: u16 },
+	RunTo(u16),
+}
+
+pub struct Debugger {
+	breakpoints: BTreeSet<u16>,
+	watchpoints: Vec<Watchpoint>,
+	mode: RunMode,
+
+	// Resuming from a breakpoint must execute the instruction under it instead of stopping again
+	ignore_break_at: Option<u16>,
+}
+
+impl Default for Debugger {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Debugger {
+	/// Creates a debugger that starts out running
+	pub fn new() -> Self {
+		Debugger {
+			breakpoints: BTreeSet::new(),
+			watchpoints: Vec::new(),
+			mode: RunMode::Running,
+			ignore_break_at: None,
+		}
+	}
+
+	pub fn paused(&self) -> bool {
+		self.mode == RunMode::Paused
+	}
+
+	pub fn pause(&mut self) {
+		self.mode = RunMode::Paused;
+	}
+
+	/// Continues running until a breakpoint is hit
+	pub fn resume(&mut self, cpu: &Cpu) {
+		self.start(cpu, RunMode::Running);
+	}
+
+	/// Executes exactly one instruction and stays paused
+	pub fn step_into(&mut self, cpu: &mut Cpu) -> StopReason {
+		self.mode = RunMode::Paused;
+		self.execute(cpu).unwrap_or(StopReason::Step)
+	}
+
+	/// Executes `count` instructions and stays paused, unless a breakpoint or watchpoint stops it
+	/// first. Frontends use this to advance a frame at a time.
+	pub fn advance(&mut self, cpu: &mut Cpu, count: usize) -> Option<StopReason> {
+		self.start(cpu, RunMode::Running);
+		let reason = self.run(cpu, count);
+		self.mode = RunMode::Paused;
+		reason
+	}
+
+	/// Like `step_into`, but a 2NNN runs the whole subroutine until it returns
+	pub fn step_over(&mut self, cpu: &mut Cpu) -> Option<StopReason> {
+		if cpu.read_word(cpu.pc()) & 0xF000 == 0x2000 {
+			let mode = RunMode::StepOver { return_to: cpu.pc().wrapping_add(2), sp: cpu.sp() };
+			self.start(cpu, mode);
+			None
+		} else {
+			Some(self.step_into(cpu))
+		}
+	}
+
+	/// Runs until the current subroutine returns to its caller
+	pub fn step_out(&mut self, cpu: &Cpu) {
+		let mode = match cpu.sp() {
+			// Not inside a subroutine, there is nothing to return from
+			0 => RunMode::Running,
+			sp => RunMode::StepOut { sp },
+		};
+		self.start(cpu, mode);
+	}
+
+	/// Runs until the cpu reaches `addr`
+	pub fn run_to(&mut self, cpu: &Cpu, addr: u16) {
+		self.start(cpu, RunMode::RunTo(addr));
+	}
+
+	fn start(&mut self, cpu: &Cpu, mode: RunMode) {
+		self.mode = mode;
+		self.ignore_break_at = Some(cpu.pc());
+	}
+
+	/// Executes up to `budget` instructions, returns why execution stopped early if it did
+	pub fn run(&mut self, cpu: &mut Cpu, budget: usize) -> Option<StopReason> {
+		for _ in 0..budget {
+			if self.mode == RunMode::Paused {
+				return None;
+			}
+
+			let pc = cpu.pc();
+			if self.ignore_break_at.take() != Some(pc) && self.breakpoints.contains(&pc) {
+				return Some(self.stop(StopReason::Breakpoint(pc)));
+			}
+
+			if let Some(reason) = self.execute(cpu) {
+				return Some(self.stop(reason));
+			}
+
+			let done = match self.mode {
+				RunMode::StepOver { return_to, sp } => cpu.pc() == return_to && cpu.sp() == sp,
+				RunMode::StepOut { sp } => cpu.sp() < sp,
+				RunMode::RunTo(addr) => cpu.pc() == addr,
+				RunMode::Running | RunMode::Paused => false,
+			};
+			if done {
+				return Some(self.stop(StopReason::Step));
+			}
+		}
+		None
+	}
+
+	fn stop(&mut self, reason: StopReason) -> StopReason {
+		self.mode = RunMode::Paused;
+		reason
+	}
+
+	// Runs one instruction and checks whether it halted or faulted the cpu or hit a watchpoint
+	fn execute(&mut self, cpu: &mut Cpu) -> Option<StopReason> {
+		if self.watchpoints.is_empty() {
+			cpu.cycle();
+		} else {
+			let before = Snapshot {
+				pc: cpu.pc(),
+				opcode: cpu.read_word(cpu.pc()),
+				v: cpu.v,
+				index: cpu.index(),
+				dt: cpu.dt,
+			};
+			cpu.cycle();
+			if let Some(hit) = self.check_watchpoints(cpu, &before) {
+				return Some(StopReason::Watchpoint(hit));
+			}
+		}
+
+		if cpu.halted() {
+			Some(StopReason::Halted)
+		} else {
+			cpu.fault().map(StopReason::Fault)
+		}
+	}
+
+	fn check_watchpoints(&self, cpu: &Cpu, before: &Snapshot) -> Option<WatchHit> {
+		for &watchpoint in &self.watchpoints {
+			let event = match watchpoint {
+				Watchpoint::Memory { start, end, access } => cpu
+					.last_accesses()
+					.iter()
+					.filter(|a| access.matches(a.kind))
+					.find_map(|a| {
+						let addr = a.addresses().find(|addr| (start..=end).contains(addr))?;
+						Some(match a.kind {
+							AccessKind::Read => WatchEvent::Read(addr),
+							AccessKind::Write => WatchEvent::Write(addr),
+						})
+					}),
+				Watchpoint::Register(reg) => {
+					let reg = (reg & 0xF) as usize;
+					changed(before.v[reg] as u16, cpu.v[reg] as u16)
+				}
+				Watchpoint::Index => changed(before.index, cpu.index()),
+				Watchpoint::DelayTimer => changed(before.dt as u16, cpu.dt as u16),
+			};
+
+			if let Some(event) = event {
+				return Some(WatchHit { watchpoint, pc: before.pc, opcode: before.opcode, event });
+			}
+		}
+		None
+	}
+}
+
+fn changed(old: u16, new: u16) -> Option<WatchEvent> {
+	if old != new {
+		Some(WatchEvent::Changed { old, new })
+	} else {
+		None
+	}
+}
+
+// Watchpoints
+impl Debugger {
+	pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+		if !self.watchpoints.contains(&watchpoint) {
+			self.watchpoints.push(watchpoint);
+		}
+	}
+
+	pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) {
+		self.watchpoints.retain(|&w| w != watchpoint);
+	}
+
+	pub fn watchpoints(&self) -> &[Watchpoint] {
+		&self.watchpoints
+	}
+
+	pub fn clear_watchpoints(&mut self) {
+		self.watchpoints.clear();
+	}
+}
+
+// Breakpoints
+impl Debugger {
+	pub fn add_breakpoint(&mut self, addr: u16) {
+		self.breakpoints.insert(addr);
+	}
+
+	pub fn remove_breakpoint(&mut self, addr: u16) {
+		self.breakpoints.remove(&addr);
+	}
+
+	pub fn toggle_breakpoint(&mut self, addr: u16) {
+		if !self.breakpoints.remove(&addr) {
+			self.breakpoints.insert(addr);
+		}
+	}
+
+	pub fn has_breakpoint(&self, addr: u16) -> bool {
+		self.breakpoints.contains(&addr)
+	}
+
+	pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+		self.breakpoints.iter().copied()
+	}
+
+	pub fn clear_breakpoints(&mut self) {
+		self.breakpoints.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cpu::cpu_with;
+
+	// 0x200: CALL 0x208
+	// 0x202: LD V1, 1
+	// 0x204: JP 0x204
+	// 0x206: (unused)
+	// 0x208: LD V2, 2
+	// 0x20A: CALL 0x210
+	// 0x20C: RET
+	// 0x210: LD V3, 3
+	// 0x212: RET
+	fn program() -> Cpu {
+		cpu_with(&[0x2208, 0x6101, 0x1204, 0x0000, 0x6202, 0x2210, 0x00EE, 0x0000, 0x6303, 0x00EE])
+	}
+
+	#[test]
+	fn breakpoint_pauses_before_instruction() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.add_breakpoint(0x202);
+
+		assert_eq!(dbg.run(&mut cpu, 100), Some(StopReason::Breakpoint(0x202)));
+		assert!(dbg.paused());
+		assert_eq!(cpu.pc(), 0x202);
+		assert_eq!(cpu.v[1], 0);
+
+		// Paused debuggers do not run anything
+		assert_eq!(dbg.run(&mut cpu, 100), None);
+		assert_eq!(cpu.pc(), 0x202);
+	}
+
+	#[test]
+	fn resume_steps_off_the_breakpoint() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.add_breakpoint(0x202);
+		dbg.run(&mut cpu, 100);
+
+		dbg.resume(&cpu);
+		assert_eq!(dbg.run(&mut cpu, 3), None);
+		assert_eq!(cpu.v[1], 1);
+		assert!(!dbg.paused());
+	}
+
+	#[test]
+	fn step_into_enters_subroutines() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.pause();
+
+		assert_eq!(dbg.step_into(&mut cpu), StopReason::Step);
+		assert_eq!(cpu.pc(), 0x208);
+		assert!(dbg.paused());
+	}
+
+	#[test]
+	fn step_over_runs_whole_call() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.pause();
+
+		assert_eq!(dbg.step_over(&mut cpu), None);
+		assert_eq!(dbg.run(&mut cpu, 100), Some(StopReason::Step));
+		assert_eq!(cpu.pc(), 0x202);
+		assert_eq!(cpu.sp(), 0);
+		assert_eq!((cpu.v[2], cpu.v[3]), (2, 3));
+
+		// Anything that is not a call is a plain step
+		assert_eq!(dbg.step_over(&mut cpu), Some(StopReason::Step));
+		assert_eq!(cpu.pc(), 0x204);
+	}
+
+	#[test]
+	fn step_over_stops_at_breakpoint_inside_call() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.pause();
+		dbg.add_breakpoint(0x210);
+
+		dbg.step_over(&mut cpu);
+		assert_eq!(dbg.run(&mut cpu, 100), Some(StopReason::Breakpoint(0x210)));
+	}
+
+	#[test]
+	fn step_out_returns_to_caller() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.pause();
+		dbg.step_into(&mut cpu);
+		dbg.step_into(&mut cpu);
+		dbg.step_into(&mut cpu);
+		assert_eq!(cpu.pc(), 0x210);
+
+		// Returns from the inner call only
+		dbg.step_out(&cpu);
+		assert_eq!(dbg.run(&mut cpu, 100), Some(StopReason::Step));
+		assert_eq!(cpu.pc(), 0x20C);
+		assert_eq!(cpu.sp(), 1);
+
+		dbg.step_out(&cpu);
+		assert_eq!(dbg.run(&mut cpu, 100), Some(StopReason::Step));
+		assert_eq!(cpu.pc(), 0x202);
+	}
+
+	#[test]
+	fn run_to_cursor() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.pause();
+
+		dbg.run_to(&cpu, 0x210);
+		assert_eq!(dbg.run(&mut cpu, 100), Some(StopReason::Step));
+		assert_eq!(cpu.pc(), 0x210);
+		assert!(dbg.paused());
+	}
+
+	#[test]
+	fn budget_limits_instructions() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		assert_eq!(dbg.run(&mut cpu, 2), None);
+		assert_eq!(cpu.pc(), 0x20A);
+	}
+
+	#[test]
+	fn advance_runs_count_and_stays_paused() {
+		let mut cpu = program();
+		let mut dbg = Debugger::new();
+		dbg.add_breakpoint(0x200);
+		dbg.add_breakpoint(0x210);
+		dbg.pause();
+
+		// Starting on a breakpoint doesn't stop it
+		assert_eq!(dbg.advance(&mut cpu, 2), None);
+		assert_eq!(cpu.pc(), 0x20A);
+		assert!(dbg.paused());
+
+		assert_eq!(dbg.advance(&mut cpu, 10), Some(StopReason::Breakpoint(0x210)));
+		assert!(dbg.paused());
+	}
+
+	#[test]
+	fn halt_pauses() {
+		let mut cpu = cpu_with(&[0x00FD]);
+		let mut dbg = Debugger::new();
+		assert_eq!(dbg.run(&mut cpu, 10), Some(StopReason::Halted));
+		assert!(dbg.paused());
+	}
+
+	#[test]
+	fn fault_pauses() {
+		let mut cpu = cpu_with(&[0x6101, 0x00EE]);
+		let mut dbg = Debugger::new();
+		assert_eq!(dbg.run(&mut cpu, 10), Some(StopReason::Fault(Fault::StackUnderflow { pc: 0x202 })));
+		assert!(dbg.paused());
+	}
+
+	#[test]
+	fn memory_write_watchpoint_reports_instruction() {
+		// LD I, 0x300 / LD V0, 5 / LD V0..V1 -> [I]
+		let mut cpu = cpu_with(&[0xA300, 0x6005, 0xF155, 0x1206]);
+		let mut dbg = Debugger::new();
+		dbg.add_watchpoint(Watchpoint::Memory { start: 0x301, end: 0x301, access: WatchAccess::Write });
+
+		let hit = match dbg.run(&mut cpu, 100) {
+			Some(StopReason::Watchpoint(hit)) => hit,
+			other => panic!("{:?}", other),
+		};
+		assert_eq!(hit.pc, 0x204);
+		assert_eq!(hit.opcode, 0xF155);
+		assert_eq!(hit.event, WatchEvent::Write(0x301));
+		assert!(dbg.paused());
+	}
+
+	#[test]
+	fn memory_read_watchpoint_ignores_writes() {
+		// LD I, 0x300 / LD [I], V0 / LD V0, [I] / DRW V0, V0, 1
+		let mut cpu = cpu_with(&[0xA300, 0xF055, 0xF065, 0xD001]);
+		let mut dbg = Debugger::new();
+		dbg.add_watchpoint(Watchpoint::Memory { start: 0x300, end: 0x30F, access: WatchAccess::Read });
+
+		assert!(matches!(dbg.run(&mut cpu, 100), Some(StopReason::Watchpoint(WatchHit { pc: 0x204, .. }))));
+		dbg.resume(&cpu);
+		assert!(matches!(dbg.run(&mut cpu, 100), Some(StopReason::Watchpoint(WatchHit { pc: 0x206, .. }))));
+	}
+
+	#[test]
+	fn register_watchpoints_only_fire_on_change() {
+		// LD V3, 0 / LD V3, 7 / LD I, 0x123 / LD DT, V3
+		let mut cpu = cpu_with(&[0x6300, 0x6307, 0xA123, 0xF315, 0x1208]);
+		let mut dbg = Debugger::new();
+		dbg.add_watchpoint(Watchpoint::Register(3));
+		dbg.add_watchpoint(Watchpoint::Index);
+		dbg.add_watchpoint(Watchpoint::DelayTimer);
+
+		let expect = |reason, pc, old, new| {
+			assert_eq!(
+				reason,
+				Some(StopReason::Watchpoint(WatchHit {
+					watchpoint: match pc {
+						0x202 => Watchpoint::Register(3),
+						0x204 => Watchpoint::Index,
+						_ => Watchpoint::DelayTimer,
+					},
+					pc,
+					opcode: match pc { 0x202 => 0x6307, 0x204 => 0xA123, _ => 0xF315 },
+					event: WatchEvent::Changed { old, new },
+				}))
+			);
+		};
+
+		expect(dbg.run(&mut cpu, 100), 0x202, 0, 7);
+		dbg.resume(&cpu);
+		expect(dbg.run(&mut cpu, 100), 0x204, 0, 0x123);
+		dbg.resume(&cpu);
+		expect(dbg.run(&mut cpu, 100), 0x206, 0, 7);
+	}
+
+	#[test]
+	fn step_into_reports_watchpoints() {
+		let mut cpu = cpu_with(&[0x6F01]);
+		let mut dbg = Debugger::new();
+		dbg.add_watchpoint(Watchpoint::Register(0xF));
+		assert!(matches!(dbg.step_into(&mut cpu), StopReason::Watchpoint(_)));
+	}
+
+	#[test]
+	fn toggle_breakpoint() {
+		let mut dbg = Debugger::new();
+		dbg.toggle_breakpoint(0x300);
+		assert!(dbg.has_breakpoint(0x300));
+		dbg.toggle_breakpoint(0x300);
+		assert!(!dbg.has_breakpoint(0x300));
+	}
+}
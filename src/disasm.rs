@@ -0,0 +1,201 @@
+// Instruction decoding for debugger views and traces
+//
+// Mnemonics follow Cowgod's technical reference, the SCHIP and XO-Chip additions use the names
+// from the Octo documentation where Cowgod has none.
+
+use crate::coverage::Coverage;
+
+/// A decoded instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+	pub addr: u16,
+	pub opcode: u16,
+	/// 4 for the XO-Chip F000 NNNN long load, 1 for data bytes and 2 for everything else
+	pub len: u16,
+	pub text: String,
+}
+
+/// Length in bytes of the instruction starting with `opcode`
+pub fn instruction_len(opcode: u16) -> u16 {
+	if opcode == 0xF000 {
+		4
+	} else {
+		2
+	}
+}
+
+/// Decodes the instruction at `addr`, reads past the end of `mem` wrap around
+pub fn decode(mem: &[u8], addr: u16) -> Instruction {
+	let word = |addr: u16| {
+		let a = mem[addr as usize % mem.len()];
+		let b = mem[addr.wrapping_add(1) as usize % mem.len()];
+		((a as u16) << 8) | b as u16
+	};
+	let opcode = word(addr);
+	let len = instruction_len(opcode);
+	let text = if len == 4 {
+		format!("LD I, {:#06X}", word(addr.wrapping_add(2)))
+	} else {
+		mnemonic(opcode)
+	};
+	Instruction { addr, opcode, len, text }
+}
+
+/// Decodes `count` instructions one after the other starting at `addr`
+pub fn decode_range(mem: &[u8], addr: u16, count: usize) -> Vec<Instruction> {
+	let mut addr = addr;
+	let mut out = Vec::with_capacity(count);
+	for _ in 0..count {
+		let ins = decode(mem, addr);
+		addr = addr.wrapping_add(ins.len);
+		out.push(ins);
+	}
+	out
+}
+
+/// Like [`decode_range`], but bytes that `coverage` saw read as data and never executed are
+/// decoded one at a time as `DB`
+pub fn decode_range_covered(mem: &[u8], coverage: &Coverage, addr: u16, count: usize) -> Vec<Instruction> {
+	let mut addr = addr;
+	let mut out = Vec::with_capacity(count);
+	for _ in 0..count {
+		let ins = if coverage.is_data(addr) {
+			let byte = mem[addr as usize % mem.len()];
+			Instruction { addr, opcode: byte as u16, len: 1, text: format!("DB {:#04X}", byte) }
+		} else {
+			decode(mem, addr)
+		};
+		addr = addr.wrapping_add(ins.len);
+		out.push(ins);
+	}
+	out
+}
+
+/// The mnemonic of a two byte instruction, unknown opcodes are shown as data
+pub fn mnemonic(opcode: u16) -> String {
+	let x = (opcode & 0x0F00) >> 8;
+	let y = (opcode & 0x00F0) >> 4;
+	let n = opcode & 0x000F;
+	let nn = opcode & 0x00FF;
+	let nnn = opcode & 0x0FFF;
+
+	match opcode & 0xF000 {
+		0x0000 => match opcode {
+			0x00E0 => "CLS".to_owned(),
+			0x00EE => "RET".to_owned(),
+			0x00FB => "SCR".to_owned(),
+			0x00FC => "SCL".to_owned(),
+			0x00FD => "EXIT".to_owned(),
+			0x00FE => "LOW".to_owned(),
+			0x00FF => "HIGH".to_owned(),
+			_ if opcode & 0xFFF0 == 0x00C0 => format!("SCD {}", n),
+			_ if opcode & 0xFFF0 == 0x00D0 => format!("SCU {}", n),
+			_ => format!("SYS {:#05X}", nnn),
+		},
+		0x1000 => format!("JP {:#05X}", nnn),
+		0x2000 => format!("CALL {:#05X}", nnn),
+		0x3000 => format!("SE V{:X}, {:#04X}", x, nn),
+		0x4000 => format!("SNE V{:X}, {:#04X}", x, nn),
+		0x5000 => match n {
+			0x0 => format!("SE V{:X}, V{:X}", x, y),
+			0x2 => format!("SAVE V{:X} - V{:X}", x, y),
+			0x3 => format!("LOAD V{:X} - V{:X}", x, y),
+			_ => data(opcode),
+		},
+		0x6000 => format!("LD V{:X}, {:#04X}", x, nn),
+		0x7000 => format!("ADD V{:X}, {:#04X}", x, nn),
+		0x8000 => match n {
+			0x0 => format!("LD V{:X}, V{:X}", x, y),
+			0x1 => format!("OR V{:X}, V{:X}", x, y),
+			0x2 => format!("AND V{:X}, V{:X}", x, y),
+			0x3 => format!("XOR V{:X}, V{:X}", x, y),
+			0x4 => format!("ADD V{:X}, V{:X}", x, y),
+			0x5 => format!("SUB V{:X}, V{:X}", x, y),
+			0x6 => format!("SHR V{:X}, V{:X}", x, y),
+			0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+			0xE => format!("SHL V{:X}, V{:X}", x, y),
+			_ => data(opcode),
+		},
+		0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+		0xA000 => format!("LD I, {:#05X}", nnn),
+		0xB000 => format!("JP V0, {:#05X}", nnn),
+		0xC000 => format!("RND V{:X}, {:#04X}", x, nn),
+		0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+		0xE000 => match nn {
+			0x9E => format!("SKP V{:X}", x),
+			0xA1 => format!("SKNP V{:X}", x),
+			_ => data(opcode),
+		},
+		0xF000 => match nn {
+			0x01 => format!("PLANE {}", x),
+			0x02 if x == 0 => "AUDIO".to_owned(),
+			0x07 => format!("LD V{:X}, DT", x),
+			0x0A => format!("LD V{:X}, K", x),
+			0x15 => format!("LD DT, V{:X}", x),
+			0x18 => format!("LD ST, V{:X}", x),
+			0x1E => format!("ADD I, V{:X}", x),
+			0x29 => format!("LD F, V{:X}", x),
+			0x30 => format!("LD HF, V{:X}", x),
+			0x33 => format!("LD B, V{:X}", x),
+			0x3A => format!("PITCH V{:X}", x),
+			0x55 => format!("LD [I], V{:X}", x),
+			0x65 => format!("LD V{:X}, [I]", x),
+			0x75 => format!("LD R, V{:X}", x),
+			0x85 => format!("LD V{:X}, R", x),
+			_ => data(opcode),
+		},
+		_ => data(opcode),
+	}
+}
+
+fn data(opcode: u16) -> String {
+	format!("DW {:#06X}", opcode)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mnemonics() {
+		assert_eq!(mnemonic(0x00E0), "CLS");
+		assert_eq!(mnemonic(0x00C4), "SCD 4");
+		assert_eq!(mnemonic(0x2ABC), "CALL 0xABC");
+		assert_eq!(mnemonic(0x3A12), "SE VA, 0x12");
+		assert_eq!(mnemonic(0x5123), "LOAD V1 - V2");
+		assert_eq!(mnemonic(0x8AB5), "SUB VA, VB");
+		assert_eq!(mnemonic(0xD125), "DRW V1, V2, 5");
+		assert_eq!(mnemonic(0xF355), "LD [I], V3");
+		assert_eq!(mnemonic(0xF201), "PLANE 2");
+	}
+
+	#[test]
+	fn unknown_opcodes_are_data() {
+		assert_eq!(mnemonic(0x5121), "DW 0x5121");
+		assert_eq!(mnemonic(0x8128), "DW 0x8128");
+		assert_eq!(mnemonic(0xE1FF), "DW 0xE1FF");
+	}
+
+	#[test]
+	fn covered_data_is_bytes() {
+		// LD I, 0x206 / DRW V0, V0, 1 / JP 0x204 / sprite
+		let mut cpu = crate::cpu::cpu_with(&[0xA206, 0xD001, 0x1204, 0x8100, 0xE000]);
+		for _ in 0..3 {
+			cpu.cycle();
+		}
+		let ins = decode_range_covered(cpu.memory(), cpu.coverage(), 0x204, 3);
+		assert_eq!(ins[1].text, "DB 0x81");
+		assert_eq!(ins[1].len, 1);
+		assert_eq!(ins[2].addr, 0x207);
+	}
+
+	#[test]
+	fn long_load_is_four_bytes() {
+		let mem = [0xF0, 0x00, 0xBE, 0xEF, 0x00, 0xE0];
+		let ins = decode_range(&mem, 0, 2);
+		assert_eq!(ins[0].text, "LD I, 0xBEEF");
+		assert_eq!(ins[0].len, 4);
+		assert_eq!(ins[1].addr, 4);
+		assert_eq!(ins[1].text, "CLS");
+	}
+}
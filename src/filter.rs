@@ -0,0 +1,167 @@
+// Display filters that hide the flicker of XOR drawing
+//
+// Chip8 games erase a sprite and draw it again somewhere else, so at any moment part of the
+// screen is missing. The filter keeps the last frames and renders a combination of them:
+// OR-blending shows a pixel lit in any of the last N frames, phosphor decay fades pixels out
+// over a few frames and vblank mode only shows the framebuffer as it was at the end of a frame.
+
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+use crate::palette::Palette;
+
+#[derive(Debug, Clone, Default)]
+pub struct DisplayFilter {
+	/// Frames OR-ed together, 0 and 1 turn blending off
+	pub blend_frames: usize,
+	/// Brightness kept by an unlit pixel each frame, between 0 and 1. `None` turns decay off
+	pub phosphor: Option<f32>,
+	/// Shows the framebuffer as it was at the last vblank instead of the live one
+	pub vblank_only: bool,
+
+	width: usize,
+	height: usize,
+	// Pixel values at the end of recent frames, the newest last
+	frames: VecDeque<Vec<u8>>,
+	// Brightness of each pixel and the value it was last lit with
+	glow: Vec<f32>,
+	glow_value: Vec<u8>,
+}
+
+impl DisplayFilter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// True if any filter is on, the output then changes every frame
+	pub fn enabled(&self) -> bool {
+		self.blend_frames > 1 || self.phosphor.is_some() || self.vblank_only
+	}
+
+	/// Records the framebuffer at the end of a 60hz frame
+	pub fn end_frame(&mut self, cpu: &Cpu) {
+		let pixels = cpu.get_graphics();
+		if (cpu.width(), cpu.height()) != (self.width, self.height) {
+			// Frames of a different resolution can't be mixed
+			self.width = cpu.width();
+			self.height = cpu.height();
+			self.frames.clear();
+			self.glow = vec![0.0; pixels.len()];
+			self.glow_value = vec![0; pixels.len()];
+		}
+
+		let keep = self.blend_frames.max(1);
+		while self.frames.len() >= keep {
+			self.frames.pop_front();
+		}
+		self.frames.push_back(pixels.to_vec());
+
+		let decay = self.phosphor.unwrap_or(0.0).clamp(0.0, 1.0);
+		for ((glow, value), &pixel) in self.glow.iter_mut().zip(self.glow_value.iter_mut()).zip(pixels) {
+			if pixel != 0 {
+				*glow = 1.0;
+				*value = pixel;
+			} else {
+				*glow *= decay;
+			}
+		}
+	}
+
+	/// Like [`Cpu::render_rgba`], with the filters applied
+	pub fn render_rgba(&self, cpu: &Cpu, palette: &Palette, out: &mut [u8]) {
+		let same_size = (cpu.width(), cpu.height()) == (self.width, self.height);
+		if !self.enabled() || !same_size {
+			cpu.render_rgba(palette, out);
+			return;
+		}
+
+		let pixels = match self.frames.back() {
+			Some(last) if self.vblank_only => last.as_slice(),
+			_ => cpu.get_graphics(),
+		};
+		assert_eq!(out.len(), pixels.len() * 4, "Output needs 4 bytes for each of the {} pixels", pixels.len());
+
+		let blended = self.blend_frames.max(1).min(self.frames.len());
+		let background = palette.color(0);
+		for (i, rgba) in out.chunks_exact_mut(4).enumerate() {
+			let value = self.frames.iter().rev().take(blended).fold(pixels[i], |acc, frame| acc | frame[i]);
+			let color = if value != 0 {
+				palette.color(value)
+			} else if self.phosphor.is_some() && self.glow[i] > 0.0 {
+				mix(background, palette.color(self.glow_value[i]), self.glow[i])
+			} else {
+				background
+			};
+			rgba.copy_from_slice(&color);
+		}
+	}
+}
+
+fn mix(from: [u8; 4], to: [u8; 4], amount: f32) -> [u8; 4] {
+	std::array::from_fn(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const WHITE: [u8; 4] = Palette::CLASSIC.colors[1];
+	const BLACK: [u8; 4] = Palette::CLASSIC.colors[0];
+
+	fn first_pixel(filter: &DisplayFilter, cpu: &Cpu) -> [u8; 4] {
+		let mut out = vec![0; cpu.width() * cpu.height() * 4];
+		filter.render_rgba(cpu, &Palette::CLASSIC, &mut out);
+		[out[0], out[1], out[2], out[3]]
+	}
+
+	#[test]
+	fn blending_keeps_recent_frames() {
+		let mut cpu = Cpu::init();
+		let mut filter = DisplayFilter { blend_frames: 2, ..DisplayFilter::new() };
+		cpu.display[0] = 1;
+		filter.end_frame(&cpu);
+		cpu.display[0] = 0;
+		assert_eq!(first_pixel(&filter, &cpu), WHITE);
+
+		filter.end_frame(&cpu);
+		// Still lit in the older of the two frames
+		assert_eq!(first_pixel(&filter, &cpu), WHITE);
+		filter.end_frame(&cpu);
+		assert_eq!(first_pixel(&filter, &cpu), BLACK);
+	}
+
+	#[test]
+	fn phosphor_decays() {
+		let mut cpu = Cpu::init();
+		let mut filter = DisplayFilter { phosphor: Some(0.5), ..DisplayFilter::new() };
+		cpu.display[0] = 1;
+		filter.end_frame(&cpu);
+		cpu.display[0] = 0;
+		filter.end_frame(&cpu);
+		assert_eq!(first_pixel(&filter, &cpu), [0x80, 0x80, 0x80, 0xFF]);
+		filter.end_frame(&cpu);
+		assert_eq!(first_pixel(&filter, &cpu), [0x40, 0x40, 0x40, 0xFF]);
+	}
+
+	#[test]
+	fn vblank_shows_last_frame() {
+		let mut cpu = Cpu::init();
+		let mut filter = DisplayFilter { vblank_only: true, ..DisplayFilter::new() };
+		cpu.display[0] = 1;
+		filter.end_frame(&cpu);
+		// Erased mid frame, the screen doesn't show it until the frame ends
+		cpu.display[0] = 0;
+		assert_eq!(first_pixel(&filter, &cpu), WHITE);
+		filter.end_frame(&cpu);
+		assert_eq!(first_pixel(&filter, &cpu), BLACK);
+	}
+
+	#[test]
+	fn disabled_filter_renders_live_display() {
+		let mut cpu = Cpu::init();
+		let mut filter = DisplayFilter::new();
+		filter.end_frame(&cpu);
+		cpu.display[0] = 1;
+		assert_eq!(first_pixel(&filter, &cpu), WHITE);
+	}
+}
@@ -0,0 +1,614 @@
+// GDB remote serial protocol stub over the debugger
+//
+// Only a single connection is served at a time and execution is driven entirely by the client.
+// Registers are sent in this order, multi byte registers little endian:
+//   V0-VF (8 bit), I (16 bit), PC (16 bit), SP (8 bit), DT (8 bit), ST (8 bit)
+// which is described to the client by the target.xml below.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::cpu::{Cpu, MEMSIZE};
+use crate::debugger::{Debugger, StopReason, WatchAccess, WatchEvent, Watchpoint};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.ultra8.chip8">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REG_COUNT: usize = 21;
+
+// Largest packet advertised to the client, memory reads are cut down to fit in one
+const PACKET_SIZE: usize = 0x4000;
+
+// Instructions run between timer ticks and checks for a client interrupt while continuing
+const CYCLES_PER_TICK: usize = 10;
+const CYCLES_PER_POLL: usize = 1000;
+
+/// Waits for a single client on `addr` and serves it until it detaches or disconnects
+pub fn listen(addr: impl ToSocketAddrs, cpu: &mut Cpu, debugger: &mut Debugger) -> io::Result<()> {
+	let listener = TcpListener::bind(addr)?;
+	let (stream, _) = listener.accept()?;
+	serve(stream, cpu, debugger)
+}
+
+/// Serves one connected client until it detaches, kills the target or disconnects
+pub fn serve(stream: TcpStream, cpu: &mut Cpu, debugger: &mut Debugger) -> io::Result<()> {
+	stream.set_nodelay(true)?;
+	debugger.pause();
+	let mut stub = Stub { stream, cpu, debugger };
+	stub.run()
+}
+
+enum Packet {
+	Command(String),
+	Interrupt,
+}
+
+struct Stub<'a> {
+	stream: TcpStream,
+	cpu: &'a mut Cpu,
+	debugger: &'a mut Debugger,
+}
+
+impl Stub<'_> {
+	fn run(&mut self) -> io::Result<()> {
+		while let Some(packet) = self.read_packet()? {
+			let command = match packet {
+				Packet::Command(command) => command,
+				// Nothing is running, just report where we are
+				Packet::Interrupt => {
+					self.send("S02")?;
+					continue;
+				}
+			};
+
+			match command.as_bytes().first() {
+				Some(b'D') => {
+					self.send("OK")?;
+					return Ok(());
+				}
+				Some(b'k') => return Ok(()),
+				_ => {
+					let reply = self.handle(&command)?;
+					self.send(&reply)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn handle(&mut self, command: &str) -> io::Result<String> {
+		// Unsupported and empty packets get an empty reply
+		let Some(kind) = command.chars().next() else {
+			return Ok(String::new());
+		};
+		let args = &command[kind.len_utf8()..];
+		let reply = match kind {
+			'?' => "S05".to_owned(),
+			'g' => (0..REG_COUNT).map(|reg| self.read_register(reg)).collect(),
+			'G' => self.write_registers(args),
+			'p' => match usize::from_str_radix(args, 16) {
+				Ok(reg) if reg < REG_COUNT => self.read_register(reg),
+				_ => "E01".to_owned(),
+			},
+			'P' => self.write_register(args),
+			'm' => self.read_memory(args),
+			'M' => self.write_memory(args),
+			'Z' => self.set_watch(args, true),
+			'z' => self.set_watch(args, false),
+			's' => {
+				let reason = self.debugger.step_into(self.cpu);
+				stop_reply(reason)
+			}
+			'c' => return self.resume(),
+			'H' => "OK".to_owned(),
+			'q' => self.query(command),
+			_ => String::new(),
+		};
+		Ok(reply)
+	}
+
+	fn query(&self, command: &str) -> String {
+		if command.starts_with("qSupported") {
+			format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+		} else if let Some(range) = command.strip_prefix("qXfer:features:read:target.xml:") {
+			match parse_pair(range, ',') {
+				Some((offset, len)) => {
+					let offset = (offset as usize).min(TARGET_XML.len());
+					let end = (offset + len as usize).min(TARGET_XML.len());
+					let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+					format!("{}{}", marker, &TARGET_XML[offset..end])
+				}
+				None => "E01".to_owned(),
+			}
+		} else if command == "qAttached" {
+			"1".to_owned()
+		} else if command == "qC" {
+			"QC1".to_owned()
+		} else if command == "qfThreadInfo" {
+			"m1".to_owned()
+		} else if command == "qsThreadInfo" {
+			"l".to_owned()
+		} else {
+			String::new()
+		}
+	}
+
+	// Runs until a breakpoint, watchpoint, halt or a ^C from the client
+	fn resume(&mut self) -> io::Result<String> {
+		self.debugger.resume(self.cpu);
+		self.stream.set_nonblocking(true)?;
+		let reply = loop {
+			if let Some(reason) = self.run_chunk() {
+				break stop_reply(reason);
+			}
+
+			let mut byte = [0];
+			match self.stream.read(&mut byte) {
+				Ok(0) => break String::new(),
+				Ok(_) if byte[0] == 0x03 => {
+					self.debugger.pause();
+					break "S02".to_owned();
+				}
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+				Err(e) => {
+					self.stream.set_nonblocking(false)?;
+					return Err(e);
+				}
+			}
+		};
+		self.stream.set_nonblocking(false)?;
+		Ok(reply)
+	}
+
+	fn run_chunk(&mut self) -> Option<StopReason> {
+		for _ in 0..CYCLES_PER_POLL / CYCLES_PER_TICK {
+			if let Some(reason) = self.debugger.run(self.cpu, CYCLES_PER_TICK) {
+				return Some(reason);
+			}
+			self.cpu.tick_timers();
+		}
+		None
+	}
+
+	fn read_register(&self, reg: usize) -> String {
+		match reg {
+			0..=15 => format!("{:02x}", self.cpu.v[reg]),
+			REG_I => hex_u16(self.cpu.index()),
+			REG_PC => hex_u16(self.cpu.pc()),
+			REG_SP => format!("{:02x}", self.cpu.sp()),
+			REG_DT => format!("{:02x}", self.cpu.dt),
+			_ => format!("{:02x}", self.cpu.st),
+		}
+	}
+
+	fn set_register(&mut self, reg: usize, bytes: &[u8]) -> bool {
+		let value = match bytes {
+			[lo] => *lo as u16,
+			[lo, hi] => u16::from_le_bytes([*lo, *hi]),
+			_ => return false,
+		};
+		match reg {
+			0..=15 => self.cpu.v[reg] = value as u8,
+			REG_I => self.cpu.set_index(value),
+			REG_PC => self.cpu.set_pc(value),
+			// The stack depth follows CALL and RET, it is not writable
+			REG_SP => {}
+			REG_DT => self.cpu.dt = value as u8,
+			REG_ST => self.cpu.st = value as u8,
+			_ => return false,
+		}
+		true
+	}
+
+	fn write_registers(&mut self, args: &str) -> String {
+		let Some(bytes) = decode_hex(args) else {
+			return "E01".to_owned();
+		};
+		let mut offset = 0;
+		for reg in 0..REG_COUNT {
+			let size = register_size(reg);
+			if offset + size > bytes.len() {
+				break;
+			}
+			self.set_register(reg, &bytes[offset..offset + size]);
+			offset += size;
+		}
+		"OK".to_owned()
+	}
+
+	fn write_register(&mut self, args: &str) -> String {
+		let parsed = args.split_once('=').and_then(|(reg, value)| {
+			let reg = usize::from_str_radix(reg, 16).ok()?;
+			Some((reg, decode_hex(value)?))
+		});
+		match parsed {
+			Some((reg, bytes)) if reg < REG_COUNT && bytes.len() == register_size(reg) => {
+				self.set_register(reg, &bytes);
+				"OK".to_owned()
+			}
+			_ => "E01".to_owned(),
+		}
+	}
+
+	fn read_memory(&self, args: &str) -> String {
+		let Some((addr, len)) = parse_pair(args, ',') else {
+			return "E01".to_owned();
+		};
+		let mem = self.cpu.memory();
+		// Two hex digits per byte
+		(0..(len as usize).min(PACKET_SIZE / 2))
+			.map(|i| format!("{:02x}", mem[(addr as usize + i) % MEMSIZE]))
+			.collect()
+	}
+
+	fn write_memory(&mut self, args: &str) -> String {
+		let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_pair(range, ',')?, decode_hex(data)?)));
+		match parsed {
+			Some(((addr, len), bytes)) if bytes.len() == len as usize => {
+				for (i, byte) in bytes.into_iter().enumerate() {
+					self.cpu.load_byte_to_memory(byte, (addr as usize + i) % MEMSIZE);
+				}
+				"OK".to_owned()
+			}
+			_ => "E01".to_owned(),
+		}
+	}
+
+	// Z0/Z1 are breakpoints, Z2/Z3/Z4 write, read and access watchpoints
+	fn set_watch(&mut self, args: &str, insert: bool) -> String {
+		let mut parts = args.split(',');
+		let kind = parts.next();
+		let addr = parts.next().and_then(|a| u32::from_str_radix(a, 16).ok());
+		let len = parts.next().and_then(|l| u32::from_str_radix(l, 16).ok()).unwrap_or(1).max(1);
+		let Some(addr) = addr else {
+			return "E01".to_owned();
+		};
+		let addr = addr as u16;
+
+		let access = match kind {
+			Some("0") | Some("1") => {
+				if insert {
+					self.debugger.add_breakpoint(addr);
+				} else {
+					self.debugger.remove_breakpoint(addr);
+				}
+				return "OK".to_owned();
+			}
+			Some("2") => WatchAccess::Write,
+			Some("3") => WatchAccess::Read,
+			Some("4") => WatchAccess::ReadWrite,
+			_ => return String::new(),
+		};
+		let watchpoint = Watchpoint::Memory { start: addr, end: addr.wrapping_add(len as u16 - 1), access };
+		if insert {
+			self.debugger.add_watchpoint(watchpoint);
+		} else {
+			self.debugger.remove_watchpoint(watchpoint);
+		}
+		"OK".to_owned()
+	}
+}
+
+// Packet framing
+impl Stub<'_> {
+	fn read_byte(&mut self) -> io::Result<Option<u8>> {
+		let mut byte = [0];
+		match self.stream.read(&mut byte)? {
+			0 => Ok(None),
+			_ => Ok(Some(byte[0])),
+		}
+	}
+
+	fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+		loop {
+			match self.read_byte()? {
+				None => return Ok(None),
+				Some(0x03) => return Ok(Some(Packet::Interrupt)),
+				Some(b'$') => {}
+				// Acks and anything between packets
+				Some(_) => continue,
+			}
+
+			let mut data = Vec::new();
+			loop {
+				match self.read_byte()? {
+					None => return Ok(None),
+					Some(b'#') => break,
+					Some(byte) => data.push(byte),
+				}
+			}
+			let mut checksum = [0; 2];
+			self.stream.read_exact(&mut checksum)?;
+
+			let expected = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+			if expected == Some(checksum_of(&data)) {
+				self.stream.write_all(b"+")?;
+				return Ok(Some(Packet::Command(String::from_utf8_lossy(&data).into_owned())));
+			}
+			self.stream.write_all(b"-")?;
+		}
+	}
+
+	fn send(&mut self, data: &str) -> io::Result<()> {
+		let mut escaped = Vec::with_capacity(data.len());
+		for &byte in data.as_bytes() {
+			if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+				escaped.push(b'}');
+				escaped.push(byte ^ 0x20);
+			} else {
+				escaped.push(byte);
+			}
+		}
+		let mut packet = Vec::with_capacity(escaped.len() + 4);
+		packet.push(b'$');
+		packet.extend_from_slice(&escaped);
+		packet.extend_from_slice(format!("#{:02x}", checksum_of(&escaped)).as_bytes());
+		self.stream.write_all(&packet)?;
+		self.stream.flush()
+	}
+}
+
+fn stop_reply(reason: StopReason) -> String {
+	match reason {
+		StopReason::Halted => "W00".to_owned(),
+		StopReason::Watchpoint(hit) => match hit.event {
+			WatchEvent::Read(addr) => format!("T05rwatch:{:x};", addr),
+			WatchEvent::Write(addr) => format!("T05watch:{:x};", addr),
+			WatchEvent::Changed { .. } => "S05".to_owned(),
+		},
+		StopReason::Breakpoint(_) | StopReason::Step => "S05".to_owned(),
+		// SIGILL, the faulting instruction is still at pc
+		StopReason::Fault(_) => "S04".to_owned(),
+	}
+}
+
+fn register_size(reg: usize) -> usize {
+	match reg {
+		REG_I | REG_PC => 2,
+		_ => 1,
+	}
+}
+
+fn hex_u16(value: u16) -> String {
+	let [lo, hi] = value.to_le_bytes();
+	format!("{:02x}{:02x}", lo, hi)
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+	data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn parse_pair(args: &str, separator: char) -> Option<(u32, u32)> {
+	let (a, b) = args.split_once(separator)?;
+	Some((u32::from_str_radix(a, 16).ok()?, u32::from_str_radix(b, 16).ok()?))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+	if !hex.len().is_multiple_of(2) {
+		return None;
+	}
+	(0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cpu::cpu_with;
+	use std::thread;
+
+	// A scripted client talking to a stub on a local socket
+	struct Client {
+		stream: TcpStream,
+	}
+
+	impl Client {
+		fn command(&mut self, data: &str) -> String {
+			let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+			self.stream.write_all(packet.as_bytes()).unwrap();
+			self.expect_byte(b'+');
+			self.reply()
+		}
+
+		fn reply(&mut self) -> String {
+			self.expect_byte(b'$');
+			let mut data = Vec::new();
+			loop {
+				match self.byte() {
+					b'#' => break,
+					b'}' => data.push(self.byte() ^ 0x20),
+					byte => data.push(byte),
+				}
+			}
+			let mut checksum = [0; 2];
+			self.stream.read_exact(&mut checksum).unwrap();
+			self.stream.write_all(b"+").unwrap();
+			String::from_utf8(data).unwrap()
+		}
+
+		fn byte(&mut self) -> u8 {
+			let mut byte = [0];
+			self.stream.read_exact(&mut byte).unwrap();
+			byte[0]
+		}
+
+		fn expect_byte(&mut self, expected: u8) {
+			assert_eq!(self.byte() as char, expected as char);
+		}
+	}
+
+	// Starts a stub for `rom` and returns the client plus a handle that yields the final cpu
+	fn start(rom: &[u16]) -> (Client, thread::JoinHandle<Cpu>) {
+		let mut cpu = cpu_with(rom);
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let handle = thread::spawn(move || {
+			let mut debugger = Debugger::new();
+			let (stream, _) = listener.accept().unwrap();
+			serve(stream, &mut cpu, &mut debugger).unwrap();
+			cpu
+		});
+		let stream = TcpStream::connect(addr).unwrap();
+		stream.set_nodelay(true).unwrap();
+		(Client { stream }, handle)
+	}
+
+	#[test]
+	fn handshake_and_target_description() {
+		let (mut client, handle) = start(&[0x1200]);
+		assert!(client.command("qSupported:xmlRegisters=i386").contains("qXfer:features:read+"));
+		assert_eq!(client.command("?"), "S05");
+
+		let xml = client.command("qXfer:features:read:target.xml:0,fff");
+		assert!(xml.starts_with('l'));
+		assert!(xml.contains("name=\"pc\""));
+
+		let part = client.command("qXfer:features:read:target.xml:0,10");
+		assert_eq!(part.len(), 0x11);
+		assert!(part.starts_with('m'));
+
+		assert_eq!(client.command("D"), "OK");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn registers_read_and_write() {
+		let (mut client, handle) = start(&[0x6A42]);
+		let regs = client.command("g");
+		assert_eq!(regs.len(), (16 + 2 + 2 + 3) * 2);
+		// PC is little endian 0x0200
+		assert_eq!(&regs[36..40], "0002");
+
+		assert_eq!(client.command("s"), "S05");
+		assert_eq!(client.command("pa"), "42");
+		assert_eq!(client.command("p11"), "0202");
+
+		assert_eq!(client.command("P3=7f"), "OK");
+		assert_eq!(client.command("P10=3412"), "OK");
+		assert_eq!(client.command("P10=34"), "E01");
+		assert_eq!(client.command("D"), "OK");
+
+		let cpu = handle.join().unwrap();
+		assert_eq!(cpu.v[3], 0x7F);
+		assert_eq!(cpu.index(), 0x1234);
+	}
+
+	#[test]
+	fn empty_and_non_ascii_packets_get_empty_replies() {
+		let (mut client, handle) = start(&[0x1200]);
+		assert_eq!(client.command(""), "");
+		assert_eq!(client.command("é1"), "");
+		assert_eq!(client.command("?"), "S05");
+		client.command("D");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn memory_read_and_write() {
+		let (mut client, handle) = start(&[0x1234]);
+		assert_eq!(client.command("m200,2"), "1234");
+		assert_eq!(client.command("M300,3:0a0b0c"), "OK");
+		assert_eq!(client.command("m300,3"), "0a0b0c");
+		assert_eq!(client.command("M300,2:0a"), "E01");
+		assert_eq!(client.command("mfff0,ffff").len(), PACKET_SIZE);
+		client.command("D");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn faults_stop_with_sigill() {
+		let (mut client, handle) = start(&[0x1200]);
+		// RET with an empty stack
+		assert_eq!(client.command("M200,2:00ee"), "OK");
+		assert_eq!(client.command("c"), "S04");
+		assert_eq!(client.command("s"), "S04");
+		assert_eq!(client.command("p11"), "0002");
+		client.command("D");
+		handle.join().unwrap();
+
+		let (mut client, handle) = start(&[0x1200]);
+		assert_eq!(client.command("M200,2:ffff"), "OK");
+		assert_eq!(client.command("s"), "S04");
+		client.command("D");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn breakpoints_and_continue() {
+		// LD V0, 1 / ADD V0, 1 / JP 0x202
+		let (mut client, handle) = start(&[0x6001, 0x7001, 0x1202]);
+		assert_eq!(client.command("Z0,202,2"), "OK");
+		assert_eq!(client.command("c"), "S05");
+		assert_eq!(client.command("p11"), "0202");
+		assert_eq!(client.command("p0"), "01");
+
+		// Continuing steps off the breakpoint and comes back around the loop
+		assert_eq!(client.command("c"), "S05");
+		assert_eq!(client.command("p0"), "02");
+
+		assert_eq!(client.command("z0,202,2"), "OK");
+		client.command("D");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn write_watchpoint_stops_continue() {
+		// LD I, 0x300 / LD [I], V0 / JP 0x204
+		let (mut client, handle) = start(&[0xA300, 0xF055, 0x1204]);
+		assert_eq!(client.command("Z2,300,1"), "OK");
+		assert_eq!(client.command("c"), "T05watch:300;");
+		client.command("D");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn interrupt_stops_running_program() {
+		let (mut client, handle) = start(&[0x1200]);
+		let packet = format!("$c#{:02x}", checksum_of(b"c"));
+		client.stream.write_all(packet.as_bytes()).unwrap();
+		client.expect_byte(b'+');
+		client.stream.write_all(&[0x03]).unwrap();
+		assert_eq!(client.reply(), "S02");
+		client.command("D");
+		handle.join().unwrap();
+	}
+
+	#[test]
+	fn halt_reports_exit() {
+		let (mut client, handle) = start(&[0x00FD]);
+		assert_eq!(client.command("c"), "W00");
+		client.command("D");
+		handle.join().unwrap();
+	}
+}
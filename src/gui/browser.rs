@@ -0,0 +1,128 @@
+// Lists the roms in the configured directories and picks one to load
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ggez_egui::egui;
+use ultra8::cpu::MAX_ROM_LEN;
+use ultra8::Platform;
+
+use super::config::{Config, CONFIG_FILE};
+
+// Documentation that ships next to roms
+const NOT_ROMS: [&str; 3] = ["doc", "txt", "md"];
+
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub platform: Platform,
+}
+
+pub struct RomBrowser {
+    pub open: bool,
+    entries: Vec<RomEntry>,
+    // Directories as they were last scanned, a rescan happens when they change
+    scanned: Vec<String>,
+    dir_input: String,
+    status: String,
+}
+
+impl RomBrowser {
+    pub fn new() -> Self {
+        RomBrowser {
+            open: false,
+            entries: Vec::new(),
+            scanned: Vec::new(),
+            dir_input: String::new(),
+            status: String::new(),
+        }
+    }
+
+    // Returns the rom to load when one was picked
+    pub fn show(&mut self, ctx: &egui::Context, config: &mut Config) -> Option<PathBuf> {
+        if self.scanned != config.browser.dirs {
+            self.refresh(&config.browser.dirs);
+        }
+        let mut picked = None;
+        let mut open = self.open;
+        egui::Window::new("Roms").open(&mut open).show(ctx, |ui| {
+            let dirs = &mut config.browser.dirs;
+            let mut remove = None;
+            for (i, dir) in dirs.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.monospace(dir);
+                    if ui.button("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                dirs.remove(i);
+            }
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.dir_input);
+                if ui.button("Add").clicked() && !self.dir_input.is_empty() {
+                    dirs.push(std::mem::take(&mut self.dir_input));
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Refresh").clicked() {
+                    self.scanned.clear();
+                }
+                if ui.button("Save").clicked() {
+                    self.status = match config.save(CONFIG_FILE) {
+                        Ok(()) => format!("Saved {}", CONFIG_FILE),
+                        Err(e) => format!("{:#}", e),
+                    };
+                }
+                ui.label(&self.status);
+            });
+            ui.separator();
+
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                egui::Grid::new("rom_list").striped(true).show(ui, |ui| {
+                    for entry in &self.entries {
+                        let name = entry.path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                        if ui.selectable_label(false, name).on_hover_text(entry.path.display().to_string()).clicked() {
+                            picked = Some(entry.path.clone());
+                        }
+                        ui.monospace(format!("{} bytes", entry.size));
+                        ui.label(entry.platform.name());
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+        self.open = open;
+        picked
+    }
+
+    fn refresh(&mut self, dirs: &[String]) {
+        self.scanned = dirs.to_vec();
+        self.entries = dirs.iter().flat_map(|dir| scan(Path::new(dir))).collect();
+        self.status = format!("{} roms", self.entries.len());
+    }
+}
+
+// The roms directly inside `dir`, sorted by name. Missing directories have none
+fn scan(dir: &Path) -> Vec<RomEntry> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<RomEntry> = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| !path.extension().is_some_and(|ext| NOT_ROMS.contains(&ext.to_string_lossy().to_lowercase().as_str())))
+        .filter_map(|path| {
+            let rom = fs::read(&path).ok()?;
+            (rom.len() <= MAX_ROM_LEN).then(|| RomEntry {
+                size: rom.len() as u64,
+                platform: Platform::detect(&rom),
+                path,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
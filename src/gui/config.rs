@@ -0,0 +1,327 @@
+// Frontend settings persisted in ultra8.toml in the working directory
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ggez::event::{Axis, Button, KeyCode};
+use serde::{Deserialize, Serialize};
+use ultra8::{DisplayFilter, Palette};
+
+use super::viewport::Scaling;
+
+pub const CONFIG_FILE: &str = "ultra8.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub keyboard: KeyboardConfig,
+    pub gamepad: GamepadConfig,
+    pub display: DisplayConfig,
+    pub browser: BrowserConfig,
+}
+
+impl Config {
+    // A missing file gives the defaults, a broken one is reported
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = toml::to_string_pretty(self).context("Failed to serialize config")?;
+        fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyboardConfig {
+    pub layout: Layout,
+    // Replaces the layout's keys when set
+    pub keys: Option<KeyMap>,
+    // Keyed by rom file name, e.g. "INVADERS"
+    pub roms: BTreeMap<String, KeyMap>,
+}
+
+impl KeyboardConfig {
+    /// The keys used by roms without an override
+    pub fn global(&self) -> KeyMap {
+        self.keys.unwrap_or_else(|| self.layout.keymap())
+    }
+
+    /// The keys used for `rom`, its override if there is one
+    pub fn keymap(&self, rom: &str) -> KeyMap {
+        self.roms.get(rom).copied().unwrap_or_else(|| self.global())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Name of a palette from `palettes` or a built in one
+    pub palette: String,
+    // Anti-flicker filters, see DisplayFilter
+    pub blend_frames: usize,
+    pub phosphor: Option<f32>,
+    pub vblank_only: bool,
+    pub scaling: Scaling,
+    pub fullscreen: bool,
+    // Custom palettes as four #RRGGBB colours: background, plane 1, plane 2, both planes
+    pub palettes: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            palette: Palette::PRESETS[0].0.to_owned(),
+            blend_frames: 0,
+            phosphor: None,
+            vblank_only: false,
+            scaling: Scaling::Integer,
+            fullscreen: false,
+            palettes: BTreeMap::new(),
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Looks `name` up in the custom palettes first, then in the built in ones
+    pub fn find_palette(&self, name: &str) -> Option<Palette> {
+        match self.palettes.get(name) {
+            Some(colors) => Palette::from_hex(colors),
+            None => Palette::preset(name),
+        }
+    }
+
+    pub fn filter(&self) -> DisplayFilter {
+        let mut filter = DisplayFilter::new();
+        filter.blend_frames = self.blend_frames;
+        filter.phosphor = self.phosphor;
+        filter.vblank_only = self.vblank_only;
+        filter
+    }
+
+    /// The selected palette, the classic one if it doesn't exist
+    pub fn palette(&self) -> Palette {
+        self.find_palette(&self.palette).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserConfig {
+    /// Directories listed in the rom browser
+    pub dirs: Vec<String>,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        BrowserConfig { dirs: vec!["roms".to_owned(), "scgames".to_owned()] }
+    }
+}
+
+/// Keypad key of each gamepad button, keyed by button name
+pub type PadMap = BTreeMap<String, u8>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    pub buttons: PadMap,
+    // Keyed by rom file name like the keyboard overrides
+    pub roms: BTreeMap<String, PadMap>,
+}
+
+impl Default for GamepadConfig {
+    // The d-pad on the usual 2/4/6/8 directions and the face buttons on the keys games use to act
+    fn default() -> Self {
+        let buttons = [
+            (Button::DPadUp, 0x2),
+            (Button::DPadDown, 0x8),
+            (Button::DPadLeft, 0x4),
+            (Button::DPadRight, 0x6),
+            (Button::South, 0x5),
+            (Button::East, 0xA),
+            (Button::West, 0x0),
+            (Button::North, 0xB),
+            (Button::Select, 0xE),
+            (Button::Start, 0xF),
+        ];
+        GamepadConfig {
+            buttons: buttons.iter().map(|&(button, key)| (button_name(button).to_owned(), key)).collect(),
+            roms: BTreeMap::new(),
+        }
+    }
+}
+
+impl GamepadConfig {
+    /// The buttons used for `rom`, its override if there is one
+    pub fn padmap(&self, rom: &str) -> &PadMap {
+        self.roms.get(rom).unwrap_or(&self.buttons)
+    }
+
+    /// The keypad key `button` presses in `rom`
+    pub fn key(&self, rom: &str, button: Button) -> Option<u8> {
+        self.padmap(rom).get(button_name(button)).copied().filter(|&key| key <= 0xF)
+    }
+}
+
+/// Physical keyboard layouts, each one puts the keypad on the same block of keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Layout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Dvorak,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 3] = [Layout::Qwerty, Layout::Azerty, Layout::Dvorak];
+
+    /// The 1234/QWER/ASDF/ZXCV block, as typed on this layout
+    pub fn keymap(self) -> KeyMap {
+        use KeyCode::*;
+        KeyMap(match self {
+            Layout::Qwerty => [X, Key1, Key2, Key3, Q, W, E, A, S, D, Z, C, Key4, R, F, V],
+            Layout::Azerty => [X, Key1, Key2, Key3, A, Z, E, Q, S, D, W, C, Key4, R, F, V],
+            Layout::Dvorak => [Q, Key1, Key2, Key3, Apostrophe, Comma, Period, A, O, E, Semicolon, J, Key4, P, U, K],
+        })
+    }
+}
+
+/// The keyboard key of each of the 16 keypad keys, indexed by keypad value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct KeyMap(pub [KeyCode; 16]);
+
+impl TryFrom<Vec<String>> for KeyMap {
+    type Error = String;
+
+    fn try_from(names: Vec<String>) -> Result<Self, String> {
+        let keys: Vec<KeyCode> = names
+            .iter()
+            .map(|name| key_from_name(name).ok_or_else(|| format!("Unknown key {}", name)))
+            .collect::<Result<_, _>>()?;
+        let keys: [KeyCode; 16] = keys.try_into().map_err(|_| "A keymap needs 16 keys".to_owned())?;
+        Ok(KeyMap(keys))
+    }
+}
+
+impl From<KeyMap> for Vec<String> {
+    fn from(map: KeyMap) -> Self {
+        map.0.iter().map(|&key| key_name(key).to_owned()).collect()
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, KeyCode)] = &[$((stringify!($key), KeyCode::$key)),*];
+    };
+}
+
+// Keys that can be bound to the keypad
+key_names!(
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Up, Down, Left, Right, Space, Return, Tab, Back, LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Apostrophe, Comma, Period, Semicolon, Slash, Backslash, Minus, Equals, LBracket, RBracket, Grave,
+);
+
+/// Name of a bindable key, "?" for keys that can't be bound
+pub fn key_name(key: KeyCode) -> &'static str {
+    KEY_NAMES.iter().find(|&&(_, k)| k == key).map_or("?", |&(name, _)| name)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEY_NAMES.iter().find(|&&(n, _)| n == name).map(|&(_, key)| key)
+}
+
+// Buttons that can be bound to the keypad
+pub const BUTTONS: [(&str, Button); 16] = [
+    ("DPadUp", Button::DPadUp),
+    ("DPadDown", Button::DPadDown),
+    ("DPadLeft", Button::DPadLeft),
+    ("DPadRight", Button::DPadRight),
+    ("South", Button::South),
+    ("East", Button::East),
+    ("West", Button::West),
+    ("North", Button::North),
+    ("LeftTrigger", Button::LeftTrigger),
+    ("LeftTrigger2", Button::LeftTrigger2),
+    ("RightTrigger", Button::RightTrigger),
+    ("RightTrigger2", Button::RightTrigger2),
+    ("Select", Button::Select),
+    ("Start", Button::Start),
+    ("LeftThumb", Button::LeftThumb),
+    ("RightThumb", Button::RightThumb),
+];
+
+pub fn button_name(button: Button) -> &'static str {
+    BUTTONS.iter().find(|&&(_, b)| b == button).map_or("?", |&(name, _)| name)
+}
+
+/// The d-pad buttons at the negative and positive ends of a left stick axis, the stick works as a d-pad
+pub fn stick_buttons(axis: Axis) -> Option<(Button, Button)> {
+    match axis {
+        Axis::LeftStickX => Some((Button::DPadLeft, Button::DPadRight)),
+        Axis::LeftStickY => Some((Button::DPadDown, Button::DPadUp)),
+        _ => None,
+    }
+}
+
+/// Name used for per-rom settings, the file name without its extension
+pub fn rom_name(path: &str) -> String {
+    Path::new(path).file_stem().map_or_else(|| path.to_owned(), |stem| stem.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymaps_round_trip() {
+        let mut config = Config::default();
+        config.keyboard.layout = Layout::Dvorak;
+        config.keyboard.roms.insert("INVADERS".to_owned(), Layout::Azerty.keymap());
+        let text = toml::to_string_pretty(&config).unwrap();
+        let back: Config = toml::from_str(&text).unwrap();
+        assert_eq!(back.keyboard.layout, Layout::Dvorak);
+        assert_eq!(back.keyboard.keymap("INVADERS"), Layout::Azerty.keymap());
+        assert_eq!(back.keyboard.keymap("PONG"), Layout::Dvorak.keymap());
+    }
+
+    #[test]
+    fn gamepad_overrides() {
+        let text = "[gamepad.roms.INVADERS]\nSouth = 5\nDPadLeft = 4\nDPadRight = 6\nEast = 99\n";
+        let config: Config = toml::from_str(text).unwrap();
+        assert_eq!(config.gamepad.key("INVADERS", Button::South), Some(5));
+        assert_eq!(config.gamepad.key("INVADERS", Button::DPadUp), None);
+        // Out of range keys are ignored
+        assert_eq!(config.gamepad.key("INVADERS", Button::East), None);
+        assert_eq!(config.gamepad.key("PONG", Button::DPadUp), Some(2));
+    }
+
+    #[test]
+    fn custom_palettes() {
+        let text = "[display]\npalette = \"Mine\"\n[display.palettes]\nMine = [\"#000000\", \"#00FF00\", \"#FF0000\", \"#0000FF\"]\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        assert_eq!(config.display.palette().colors[1], [0, 0xFF, 0, 0xFF]);
+        config.display.palette = "Amber".to_owned();
+        assert_eq!(config.display.palette(), Palette::AMBER);
+        config.display.palette = "Missing".to_owned();
+        assert_eq!(config.display.palette(), Palette::CLASSIC);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let text = "[keyboard]\nkeys = [\"Q\"]\n";
+        assert!(toml::from_str::<Config>(text).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+// Disassembly around the program counter with a breakpoint gutter, bytes the rom has only
+// used as data so far are shown as DB
+
+use ggez_egui::egui::{self, Color32, RichText};
+use ultra8::{disasm, Cpu, Debugger};
+
+// Instructions shown before and after the current one
+const BEFORE: u16 = 8;
+const LINES: usize = 24;
+
+const CURRENT_BACKGROUND: Color32 = Color32::from_rgb(120, 90, 0);
+const BREAKPOINT_COLOR: Color32 = Color32::from_rgb(220, 50, 50);
+
+pub struct DisassemblyView {
+    pub open: bool,
+}
+
+impl DisassemblyView {
+    pub fn new() -> Self {
+        DisassemblyView { open: true }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, cpu: &Cpu, debugger: &mut Debugger) {
+        let mut open = self.open;
+        egui::Window::new("Disassembly").open(&mut open).show(ctx, |ui| {
+            // Instructions are not aligned to anything, so start decoding on the same byte parity as pc
+            let start = cpu.pc().saturating_sub(BEFORE * 2);
+            let mut toggle = None;
+            for ins in disasm::decode_range_covered(cpu.memory(), cpu.coverage(), start, LINES) {
+                ui.horizontal(|ui| {
+                    let marker = if debugger.has_breakpoint(ins.addr) {
+                        RichText::new("●").color(BREAKPOINT_COLOR)
+                    } else {
+                        RichText::new("○").weak()
+                    };
+                    if ui.add(egui::Label::new(marker).sense(egui::Sense::click())).on_hover_text("Toggle breakpoint").clicked() {
+                        toggle = Some(ins.addr);
+                    }
+
+                    let bytes = if ins.len == 1 { format!("{:02X}  ", ins.opcode) } else { format!("{:04X}", ins.opcode) };
+                    let line = format!("{:04X}  {}  {}", ins.addr, bytes, ins.text);
+                    let mut text = RichText::new(line).monospace();
+                    if ins.addr == cpu.pc() {
+                        text = text.background_color(CURRENT_BACKGROUND);
+                    }
+                    ui.label(text);
+                });
+            }
+            if let Some(addr) = toggle {
+                debugger.toggle_breakpoint(addr);
+            }
+        });
+        self.open = open;
+    }
+}
@@ -0,0 +1,116 @@
+// Display settings: palette selection and editing, window scaling and the anti-flicker filters
+
+use ggez_egui::egui::{self, Color32};
+use ultra8::{DisplayFilter, Palette};
+
+use super::config::{Config, CONFIG_FILE};
+use super::viewport::Scaling;
+
+const COLOR_NAMES: [&str; 4] = ["Background", "Plane 1", "Plane 2", "Both planes"];
+
+pub struct DisplaySettings {
+    pub open: bool,
+    // Name the edited colours are saved under
+    name_input: String,
+    status: String,
+}
+
+impl DisplaySettings {
+    pub fn new() -> Self {
+        DisplaySettings {
+            open: false,
+            name_input: "Custom".to_owned(),
+            status: String::new(),
+        }
+    }
+
+    // Returns true when the palette or the filters changed
+    pub fn show(&mut self, ctx: &egui::Context, config: &mut Config, palette: &mut Palette, filter: &mut DisplayFilter) -> bool {
+        let mut changed = false;
+        let mut open = self.open;
+        egui::Window::new("Display").open(&mut open).show(ctx, |ui| {
+            let display = &mut config.display;
+            let names: Vec<String> = Palette::PRESETS
+                .iter()
+                .map(|&(name, _)| name.to_owned())
+                .chain(display.palettes.keys().cloned())
+                .collect();
+            egui::ComboBox::from_label("Palette").selected_text(&display.palette).show_ui(ui, |ui| {
+                for name in names {
+                    if ui.selectable_label(display.palette == name, &name).clicked() {
+                        display.palette = name;
+                        *palette = display.palette();
+                        changed = true;
+                    }
+                }
+            });
+
+            for (color, name) in palette.colors.iter_mut().zip(COLOR_NAMES) {
+                ui.horizontal(|ui| {
+                    let mut c = Color32::from_rgb(color[0], color[1], color[2]);
+                    if ui.color_edit_button_srgba(&mut c).changed() {
+                        *color = [c.r(), c.g(), c.b(), 0xFF];
+                        changed = true;
+                    }
+                    ui.label(name);
+                });
+            }
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.name_input);
+                if ui.button("Keep colours").on_hover_text("Add the edited colours as a palette").clicked() {
+                    config.display.palettes.insert(self.name_input.clone(), palette.to_hex());
+                    config.display.palette = self.name_input.clone();
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Scaling");
+                for scaling in Scaling::ALL {
+                    ui.radio_value(&mut config.display.scaling, scaling, format!("{:?}", scaling));
+                }
+            });
+            ui.checkbox(&mut config.display.fullscreen, "Fullscreen (Alt+Enter)");
+            ui.separator();
+            if self.filters(ui, config) {
+                *filter = config.display.filter();
+                changed = true;
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.status = match config.save(CONFIG_FILE) {
+                        Ok(()) => format!("Saved {}", CONFIG_FILE),
+                        Err(e) => format!("{:#}", e),
+                    };
+                }
+                ui.label(&self.status);
+            });
+        });
+        self.open = open;
+        changed
+    }
+
+    fn filters(&mut self, ui: &mut egui::Ui, config: &mut Config) -> bool {
+        let display = &mut config.display;
+        let mut changed = false;
+        let mut blend = display.blend_frames > 1;
+        ui.horizontal(|ui| {
+            changed |= ui.checkbox(&mut blend, "Blend frames").changed();
+            let mut frames = display.blend_frames.max(2);
+            changed |= ui.add_enabled(blend, egui::Slider::new(&mut frames, 2..=8)).changed();
+            display.blend_frames = if blend { frames } else { 0 };
+        });
+
+        let mut phosphor = display.phosphor.is_some();
+        ui.horizontal(|ui| {
+            changed |= ui.checkbox(&mut phosphor, "Phosphor decay").changed();
+            let mut decay = display.phosphor.unwrap_or(0.6);
+            changed |= ui.add_enabled(phosphor, egui::Slider::new(&mut decay, 0.1..=0.95)).changed();
+            display.phosphor = phosphor.then_some(decay);
+        });
+
+        changed |= ui.checkbox(&mut display.vblank_only, "Draw only on vblank").changed();
+        changed
+    }
+}
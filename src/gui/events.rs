@@ -0,0 +1,116 @@
+// The ggez main loop, with dropped files passed on
+//
+// `ggez::event::run` ignores winit's DroppedFile event, so this is the same loop with one more
+// callback. Gamepad ids can only be looked up through the connected gamepads, the way ggez
+// hands them out.
+
+use std::path::PathBuf;
+
+use ggez::event::winit_event::{ElementState, Event, KeyboardInput, MouseScrollDelta, WindowEvent};
+use ggez::event::{self, ControlFlow, EventHandler, EventLoop, GamepadId};
+use ggez::input::gamepad::{self, gilrs};
+use ggez::input::{keyboard, mouse};
+use ggez::{Context, GameError};
+
+pub trait FileDropHandler {
+    fn file_dropped(&mut self, ctx: &mut Context, path: PathBuf);
+}
+
+pub fn run<S>(mut ctx: Context, event_loop: EventLoop<()>, mut state: S) -> !
+where
+    S: EventHandler<GameError> + FileDropHandler + 'static,
+{
+    event_loop.run(move |mut event, _, control_flow| {
+        if !ctx.continuing {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+        *control_flow = ControlFlow::Poll;
+
+        let ctx = &mut ctx;
+        event::process_event(ctx, &mut event);
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::Resized(size) => state.resize_event(ctx, size.width as f32, size.height as f32),
+                WindowEvent::CloseRequested if !state.quit_event(ctx) => event::quit(ctx),
+                WindowEvent::Focused(gained) => state.focus_event(ctx, gained),
+                WindowEvent::ReceivedCharacter(ch) => state.text_input_event(ctx, ch),
+                WindowEvent::DroppedFile(path) => state.file_dropped(ctx, path),
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput { state: key_state, virtual_keycode: Some(keycode), .. },
+                    ..
+                } => {
+                    let mods = keyboard::active_mods(ctx);
+                    match key_state {
+                        ElementState::Pressed => {
+                            let repeat = keyboard::is_key_repeated(ctx);
+                            state.key_down_event(ctx, keycode, mods, repeat);
+                        }
+                        ElementState::Released => state.key_up_event(ctx, keycode, mods),
+                    }
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let (x, y) = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => (x, y),
+                        MouseScrollDelta::PixelDelta(pos) => {
+                            let scale_factor = ggez::graphics::window(ctx).scale_factor();
+                            let pos = pos.to_logical::<f32>(scale_factor);
+                            (pos.x, pos.y)
+                        }
+                    };
+                    state.mouse_wheel_event(ctx, x, y);
+                }
+                WindowEvent::MouseInput { state: button_state, button, .. } => {
+                    let position = mouse::position(ctx);
+                    match button_state {
+                        ElementState::Pressed => state.mouse_button_down_event(ctx, button, position.x, position.y),
+                        ElementState::Released => state.mouse_button_up_event(ctx, button, position.x, position.y),
+                    }
+                }
+                WindowEvent::CursorMoved { .. } => {
+                    let position = mouse::position(ctx);
+                    let delta = mouse::delta(ctx);
+                    state.mouse_motion_event(ctx, position.x, position.y, delta.x, delta.y);
+                }
+                _ => {}
+            },
+            Event::MainEventsCleared => {
+                ctx.timer_context.tick();
+
+                // The gamepad module is on, ContextBuilder's default
+                while let Some(gilrs::Event { id, event, .. }) = ctx.gamepad_context.next_event() {
+                    let Some(id) = gamepad_id(ctx, id) else {
+                        continue;
+                    };
+                    match event {
+                        gilrs::EventType::ButtonPressed(button, _) => state.gamepad_button_down_event(ctx, button, id),
+                        gilrs::EventType::ButtonReleased(button, _) => state.gamepad_button_up_event(ctx, button, id),
+                        gilrs::EventType::AxisChanged(axis, value, _) => state.gamepad_axis_event(ctx, axis, value, id),
+                        _ => {}
+                    }
+                }
+
+                if let Err(e) = state.update(ctx) {
+                    eprintln!("Error on EventHandler::update(): {:?}", e);
+                    if state.on_error(ctx, event::ErrorOrigin::Update, e) {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+                if let Err(e) = state.draw(ctx) {
+                    eprintln!("Error on EventHandler::draw(): {:?}", e);
+                    if state.on_error(ctx, event::ErrorOrigin::Draw, e) {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+                ctx.mouse_context.reset_delta();
+            }
+            _ => {}
+        }
+    })
+}
+
+fn gamepad_id(ctx: &Context, id: gilrs::GamepadId) -> Option<GamepadId> {
+    gamepad::gamepads(ctx).find(|(_, pad)| pad.id() == id).map(|(id, _)| id)
+}
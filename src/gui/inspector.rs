@@ -0,0 +1,123 @@
+// Machine state window, replaces the old "CHIP8 Registers" debug print
+
+use ggez_egui::egui::{self, Color32, RichText};
+use ultra8::{Cpu, VideoMode};
+
+// Keypad in the COSMAC VIP layout
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+const PRESSED_BACKGROUND: Color32 = Color32::from_rgb(60, 140, 60);
+
+pub struct Inspector {
+    pub open: bool,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Inspector { open: true }
+    }
+
+    // Values can only be edited while the debugger is paused
+    pub fn show(&mut self, ctx: &egui::Context, cpu: &mut Cpu, paused: bool) {
+        let mut open = self.open;
+        egui::Window::new("CHIP8 Registers").open(&mut open).show(ctx, |ui| {
+            ui.add_enabled_ui(paused, |ui| {
+                egui::Grid::new("registers").num_columns(6).striped(true).show(ui, |ui| {
+                    // Two columns of V registers
+                    for row in 0..8 {
+                        for reg in [row, row + 8] {
+                            ui.monospace(format!("V{:X}", reg));
+                            ui.add(egui::DragValue::new(&mut cpu.v[reg]));
+                            ui.monospace(format!("{:02X}", cpu.v[reg]));
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+
+                egui::Grid::new("special").num_columns(3).show(ui, |ui| {
+                    let mut pc = cpu.pc();
+                    ui.monospace("PC");
+                    if ui.add(egui::DragValue::new(&mut pc)).changed() {
+                        cpu.set_pc(pc);
+                    }
+                    ui.monospace(format!("{:04X}", pc));
+                    ui.end_row();
+
+                    let mut index = cpu.index();
+                    ui.monospace("I");
+                    if ui.add(egui::DragValue::new(&mut index)).changed() {
+                        cpu.set_index(index);
+                    }
+                    ui.monospace(format!("{:04X}", index));
+                    ui.end_row();
+
+                    ui.monospace("DT");
+                    ui.add(egui::DragValue::new(&mut cpu.dt));
+                    ui.monospace(format!("{:02X}", cpu.dt));
+                    ui.end_row();
+
+                    ui.monospace("ST");
+                    ui.add(egui::DragValue::new(&mut cpu.st));
+                    ui.monospace(format!("{:02X}", cpu.st));
+                    ui.end_row();
+
+                    ui.monospace("SP");
+                    ui.monospace(cpu.sp().to_string());
+                    ui.end_row();
+                });
+                ui.separator();
+
+                ui.label("Stack");
+                let stack: Vec<u16> = cpu.stack().to_vec();
+                if stack.is_empty() {
+                    ui.weak("empty");
+                }
+                // Most recent return address first
+                for (depth, &addr) in stack.iter().enumerate().rev() {
+                    let mut addr = addr;
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("{:2}", depth));
+                        if ui.add(egui::DragValue::new(&mut addr)).changed() {
+                            cpu.set_stack_entry(depth, addr);
+                        }
+                        ui.monospace(format!("{:04X}", addr));
+                    });
+                }
+            });
+            ui.separator();
+
+            let mode = match cpu.video_mode() {
+                VideoMode::Lores => "Lores",
+                VideoMode::Hires => "Hires",
+            };
+            ui.label(format!("Video: {} {}x{}  Planes: {:02b}", mode, cpu.width(), cpu.height(), cpu.planes()));
+            if cpu.halted() {
+                ui.label("Halted");
+            }
+            if let Some(fault) = cpu.fault() {
+                ui.label(fault.to_string());
+            }
+
+            ui.label("Keypad");
+            egui::Grid::new("keypad").show(ui, |ui| {
+                for row in KEYPAD_LAYOUT {
+                    for key in row {
+                        let mut text = RichText::new(format!(" {:X} ", key)).monospace();
+                        if cpu.keypad[key as usize] != 0 {
+                            text = text.background_color(PRESSED_BACKGROUND);
+                        }
+                        ui.label(text);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        self.open = open;
+    }
+}
@@ -0,0 +1,140 @@
+// Keyboard and gamepad bindings of the keypad, globally or for the loaded rom
+
+use ggez::event::KeyCode;
+use ggez_egui::egui;
+
+use super::config::{key_from_name, key_name, Config, Layout, BUTTONS, CONFIG_FILE};
+
+// The keypad as laid out on the COSMAC VIP
+const GRID: [[usize; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+pub struct KeypadSettings {
+    pub open: bool,
+    // Keypad key waiting for a keyboard key to be pressed
+    capturing: Option<usize>,
+    status: String,
+}
+
+impl KeypadSettings {
+    pub fn new() -> Self {
+        KeypadSettings {
+            open: false,
+            capturing: None,
+            status: String::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, config: &mut Config, rom: &str) {
+        let mut open = self.open;
+        egui::Window::new("Keypad").open(&mut open).show(ctx, |ui| {
+            let keyboard = &mut config.keyboard;
+            ui.horizontal(|ui| {
+                ui.label("Layout");
+                for layout in Layout::ALL {
+                    if ui.radio_value(&mut keyboard.layout, layout, format!("{:?}", layout)).clicked() {
+                        // Picking a layout drops any custom global bindings
+                        keyboard.keys = None;
+                    }
+                }
+            });
+
+            let mut per_rom = keyboard.roms.contains_key(rom);
+            if ui.checkbox(&mut per_rom, format!("Separate keys for {}", rom)).changed() {
+                if per_rom {
+                    keyboard.roms.insert(rom.to_owned(), keyboard.global());
+                } else {
+                    keyboard.roms.remove(rom);
+                }
+            }
+
+            let keymap = keyboard.keymap(rom);
+            egui::Grid::new("keypad_grid").show(ui, |ui| {
+                for row in GRID {
+                    for key in row {
+                        let label = if self.capturing == Some(key) {
+                            format!("{:X}: ...", key)
+                        } else {
+                            format!("{:X}: {}", key, key_name(keymap.0[key]))
+                        };
+                        if ui.add(egui::Button::new(label).wrap(false)).on_hover_text("Click, then press a key").clicked() {
+                            self.capturing = Some(key);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            self.gamepad(ui, config, rom);
+
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.status = match config.save(CONFIG_FILE) {
+                        Ok(()) => format!("Saved {}", CONFIG_FILE),
+                        Err(e) => format!("{:#}", e),
+                    };
+                }
+                ui.label(&self.status);
+            });
+        });
+        self.open = open;
+        if !self.open {
+            self.capturing = None;
+        }
+    }
+
+    fn gamepad(&mut self, ui: &mut egui::Ui, config: &mut Config, rom: &str) {
+        let gamepad = &mut config.gamepad;
+        ui.label("Gamepad");
+        let mut per_rom = gamepad.roms.contains_key(rom);
+        if ui.checkbox(&mut per_rom, format!("Separate buttons for {}", rom)).changed() {
+            if per_rom {
+                gamepad.roms.insert(rom.to_owned(), gamepad.buttons.clone());
+            } else {
+                gamepad.roms.remove(rom);
+            }
+        }
+
+        let padmap = gamepad.roms.get_mut(rom).unwrap_or(&mut gamepad.buttons);
+        egui::Grid::new("gamepad_grid").show(ui, |ui| {
+            for (i, (name, _)) in BUTTONS.iter().enumerate() {
+                let mut key = padmap.get(*name).copied();
+                let text = key.map_or("-".to_owned(), |key| format!("{:X}", key));
+                ui.label(*name);
+                egui::ComboBox::from_id_source(name).selected_text(text).width(40.0).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut key, None, "-");
+                    for k in 0..16u8 {
+                        ui.selectable_value(&mut key, Some(k), format!("{:X}", k));
+                    }
+                });
+                match key {
+                    Some(key) => padmap.insert(name.to_string(), key),
+                    None => padmap.remove(*name),
+                };
+                if i % 2 == 1 {
+                    ui.end_row();
+                }
+            }
+        });
+    }
+
+    // Binds `key` if a keypad key is waiting for one, returns false when the key was not used
+    pub fn capture(&mut self, key: KeyCode, config: &mut Config, rom: &str) -> bool {
+        let Some(index) = self.capturing.take() else {
+            return false;
+        };
+        // Escape and other keys without a name cancel the rebind
+        if key_from_name(key_name(key)).is_none() {
+            return true;
+        }
+        let keyboard = &mut config.keyboard;
+        let mut keymap = keyboard.keymap(rom);
+        keymap.0[index] = key;
+        if keyboard.roms.contains_key(rom) {
+            keyboard.roms.insert(rom.to_owned(), keymap);
+        } else {
+            keyboard.keys = Some(keymap);
+        }
+        true
+    }
+}
@@ -0,0 +1,230 @@
+// Hex/ASCII view of the whole address space, opened with the "Dump Ram" button
+
+use std::fs;
+
+use ggez_egui::egui::{self, Color32, RichText, TextStyle};
+use ultra8::cpu::{BIG_FONT_START, FONT_START, MEMSIZE, PROGRAM_START};
+use ultra8::Cpu;
+
+use super::parse_addr;
+
+const BYTES_PER_ROW: usize = 16;
+const ROWS: usize = MEMSIZE / BYTES_PER_ROW;
+
+// Small font followed by the 16 big font glyphs
+const FONT_END: usize = BIG_FONT_START + 16 * 10;
+
+const FONT_COLOR: Color32 = Color32::from_rgb(90, 140, 255);
+const PROGRAM_COLOR: Color32 = Color32::from_rgb(110, 200, 110);
+// Rom bytes that have not been executed, read or written yet
+const UNTOUCHED_COLOR: Color32 = Color32::from_rgb(60, 110, 60);
+const PC_BACKGROUND: Color32 = Color32::from_rgb(120, 90, 0);
+const INDEX_BACKGROUND: Color32 = Color32::from_rgb(0, 80, 120);
+const SELECTED_BACKGROUND: Color32 = Color32::from_rgb(90, 90, 90);
+const HEAT_COLOR: Color32 = Color32::from_rgb(200, 40, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Follow {
+    None,
+    Pc,
+    Index,
+}
+
+pub struct MemoryView {
+    pub open: bool,
+    follow: Follow,
+    selected: Option<u16>,
+    value_input: String,
+    goto_input: String,
+    // Row to scroll to on the next frame
+    scroll_to: Option<usize>,
+    file_input: String,
+    coverage_input: String,
+    status: String,
+    heatmap: bool,
+}
+
+impl MemoryView {
+    pub fn new() -> Self {
+        MemoryView {
+            open: false,
+            follow: Follow::Pc,
+            selected: None,
+            value_input: String::new(),
+            goto_input: String::new(),
+            scroll_to: None,
+            file_input: "ram.bin".to_owned(),
+            coverage_input: "coverage.bin".to_owned(),
+            status: String::new(),
+            heatmap: true,
+        }
+    }
+
+    // Memory can only be edited while the debugger is paused
+    pub fn show(&mut self, ctx: &egui::Context, cpu: &mut Cpu, paused: bool) {
+        let mut open = self.open;
+        egui::Window::new("Memory").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Follow");
+                ui.radio_value(&mut self.follow, Follow::None, "None");
+                ui.radio_value(&mut self.follow, Follow::Pc, "PC");
+                ui.radio_value(&mut self.follow, Follow::Index, "I");
+                ui.separator();
+                ui.label("Go to");
+                if ui.text_edit_singleline(&mut self.goto_input).lost_focus() {
+                    if let Some(addr) = parse_addr(&self.goto_input) {
+                        self.follow = Follow::None;
+                        self.scroll_to = Some(addr as usize / BYTES_PER_ROW);
+                    }
+                }
+                if cpu.profiler().is_some() {
+                    ui.separator();
+                    ui.checkbox(&mut self.heatmap, "Heatmap");
+                }
+            });
+
+            self.edit_row(ui, cpu, paused);
+            self.file_row(ui, cpu, paused);
+            ui.separator();
+            self.grid(ui, cpu);
+        });
+        self.open = open;
+    }
+
+    fn edit_row(&mut self, ui: &mut egui::Ui, cpu: &mut Cpu, paused: bool) {
+        ui.horizontal(|ui| match self.selected {
+            Some(addr) => {
+                ui.monospace(format!("{:04X}:", addr));
+                ui.add_enabled(paused, egui::TextEdit::singleline(&mut self.value_input).desired_width(30.0));
+                if ui.add_enabled(paused, egui::Button::new("Write")).clicked() {
+                    match u8::from_str_radix(self.value_input.trim(), 16) {
+                        Ok(value) => {
+                            cpu.load_byte_to_memory(value, addr as usize);
+                            // Move on to the next byte so sequences can be typed in quickly
+                            self.select(cpu, addr.wrapping_add(1));
+                        }
+                        Err(_) => self.status = format!("{} is not a hex byte", self.value_input),
+                    }
+                }
+                if !paused {
+                    ui.label("Pause to edit");
+                }
+            }
+            None => {
+                ui.label("Click a byte to edit it");
+            }
+        });
+    }
+
+    fn file_row(&mut self, ui: &mut egui::Ui, cpu: &mut Cpu, paused: bool) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.file_input);
+            if ui.button("Export").clicked() {
+                self.status = match fs::write(&self.file_input, cpu.memory()) {
+                    Ok(()) => format!("Saved {} bytes to {}", MEMSIZE, self.file_input),
+                    Err(e) => format!("Export failed: {}", e),
+                };
+            }
+            if ui.add_enabled(paused, egui::Button::new("Import")).clicked() {
+                self.status = match fs::read(&self.file_input) {
+                    Ok(data) => {
+                        cpu.load_memory(&data);
+                        format!("Loaded {} bytes from {}", data.len().min(MEMSIZE), self.file_input)
+                    }
+                    Err(e) => format!("Import failed: {}", e),
+                };
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.coverage_input);
+            if ui.button("Export coverage").clicked() {
+                self.status = match cpu.coverage().save(&self.coverage_input) {
+                    Ok(()) => format!("Saved coverage to {}", self.coverage_input),
+                    Err(e) => format!("Export failed: {}", e),
+                };
+            }
+            if ui.button("Clear coverage").clicked() {
+                cpu.clear_coverage();
+            }
+        });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+
+    fn grid(&mut self, ui: &mut egui::Ui, cpu: &Cpu) {
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + ui.spacing().item_spacing.y;
+        let program_end = PROGRAM_START + cpu.rom_len();
+        let heat = cpu.profiler().filter(|_| self.heatmap);
+        // Counts are shown on a log scale so tight loops don't drown out everything else
+        let max_heat = heat.map_or(0.0, |p| (p.counts().iter().max().copied().unwrap_or(0) as f32).ln_1p());
+
+        let target = match self.follow {
+            Follow::Pc => Some(cpu.pc() as usize / BYTES_PER_ROW),
+            Follow::Index => Some(cpu.index() as usize / BYTES_PER_ROW),
+            Follow::None => self.scroll_to.take(),
+        };
+
+        let mut area = egui::ScrollArea::vertical().id_source("memory_grid");
+        if let Some(row) = target {
+            // Keep a couple of rows of context above the followed address
+            area = area.vertical_scroll_offset(row.saturating_sub(2) as f32 * row_height);
+        }
+
+        let mut clicked = None;
+        area.show_rows(ui, row_height, ROWS, |ui, rows| {
+            for row in rows {
+                let base = row * BYTES_PER_ROW;
+                let bytes = &cpu.memory()[base..base + BYTES_PER_ROW];
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    ui.monospace(format!("{:04X}", base));
+                    for (i, &byte) in bytes.iter().enumerate() {
+                        let addr = base + i;
+                        let mut text = RichText::new(format!("{:02X}", byte)).monospace();
+                        if (FONT_START..FONT_END).contains(&addr) {
+                            text = text.color(FONT_COLOR);
+                        } else if (PROGRAM_START..program_end).contains(&addr) {
+                            let touched = cpu.coverage().flags(addr as u16) != 0;
+                            text = text.color(if touched { PROGRAM_COLOR } else { UNTOUCHED_COLOR });
+                        }
+
+                        // Instructions are two bytes long
+                        if addr == cpu.pc() as usize || addr == cpu.pc() as usize + 1 {
+                            text = text.background_color(PC_BACKGROUND);
+                        } else if addr == cpu.index() as usize {
+                            text = text.background_color(INDEX_BACKGROUND);
+                        } else if self.selected == Some(addr as u16) {
+                            text = text.background_color(SELECTED_BACKGROUND);
+                        } else if let Some(profiler) = heat {
+                            let count = profiler.count(addr as u16);
+                            if count > 0 {
+                                let heat = (count as f32).ln_1p() / max_heat;
+                                text = text.background_color(HEAT_COLOR.linear_multiply(0.2 + 0.8 * heat));
+                            }
+                        }
+
+                        if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                            clicked = Some(addr as u16);
+                        }
+                    }
+                    let ascii: String = bytes
+                        .iter()
+                        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                        .collect();
+                    ui.monospace(ascii);
+                });
+            }
+        });
+
+        if let Some(addr) = clicked {
+            self.select(cpu, addr);
+        }
+    }
+
+    fn select(&mut self, cpu: &Cpu, addr: u16) {
+        self.selected = Some(addr);
+        self.value_input = format!("{:02X}", cpu.memory()[addr as usize]);
+    }
+}
+
@@ -0,0 +1,21 @@
+// egui windows and settings of the ggez frontend
+
+pub mod browser;
+pub mod config;
+pub mod disassembly;
+pub mod display;
+pub mod events;
+pub mod inspector;
+pub mod keypad;
+pub mod memory;
+pub mod profiler;
+pub mod speed;
+pub mod trace;
+pub mod viewport;
+
+// Accepts addresses like 2A0, 0x2A0 or 0X2A0
+pub fn parse_addr(input: &str) -> Option<u16> {
+    let input = input.trim();
+    let hex = input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")).unwrap_or(input);
+    u16::from_str_radix(hex, 16).ok()
+}
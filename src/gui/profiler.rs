@@ -0,0 +1,65 @@
+// Hottest addresses and subroutines of the running rom
+
+use ggez_egui::egui;
+use ultra8::disasm;
+use ultra8::{Cpu, Profiler};
+
+const ENTRIES: usize = 20;
+
+pub struct ProfilerWindow {
+    pub open: bool,
+}
+
+impl ProfilerWindow {
+    pub fn new() -> Self {
+        ProfilerWindow { open: false }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, cpu: &mut Cpu) {
+        let mut open = self.open;
+        egui::Window::new("Profiler").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if cpu.profiler().is_some() {
+                    if ui.button("Stop").clicked() {
+                        cpu.set_profiler(None);
+                    }
+                    if ui.button("Reset").clicked() {
+                        cpu.set_profiler(Some(Profiler::new()));
+                    }
+                } else if ui.button("Start").clicked() {
+                    cpu.set_profiler(Some(Profiler::new()));
+                }
+            });
+
+            let Some(profiler) = cpu.profiler() else {
+                ui.label("Start the profiler to count executed instructions");
+                return;
+            };
+            let total = profiler.total().max(1) as f64;
+            ui.label(format!("{} instructions executed", profiler.total()));
+
+            ui.heading("Addresses");
+            egui::Grid::new("profiler_addresses").striped(true).show(ui, |ui| {
+                for (addr, count) in profiler.hottest(ENTRIES) {
+                    ui.monospace(format!("{:04X}", addr));
+                    ui.monospace(disasm::decode(cpu.memory(), addr).text);
+                    ui.monospace(count.to_string());
+                    ui.monospace(format!("{:.2}%", count as f64 * 100.0 / total));
+                    ui.end_row();
+                }
+            });
+
+            ui.heading("Subroutines");
+            egui::Grid::new("profiler_subroutines").striped(true).show(ui, |ui| {
+                for (addr, stats) in profiler.subroutines().into_iter().take(ENTRIES) {
+                    ui.monospace(format!("{:04X}", addr));
+                    ui.monospace(format!("{} calls", stats.calls));
+                    ui.monospace(stats.instructions.to_string());
+                    ui.monospace(format!("{:.2}%", stats.instructions as f64 * 100.0 / total));
+                    ui.end_row();
+                }
+            });
+        });
+        self.open = open;
+    }
+}
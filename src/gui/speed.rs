@@ -0,0 +1,81 @@
+// Paces emulation in 60hz frames independently of the window's frame rate
+//
+// Every frame runs a fixed number of instructions followed by one timer tick, so changing the
+// speed changes how fast frames come, never how many instructions happen per timer tick.
+
+use std::time::Duration;
+
+use ggez_egui::egui;
+use ultra8::Platform;
+
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Frames caught up in one update at most, so a long stall doesn't turn into a burst
+const MAX_CATCH_UP: u32 = 4;
+
+// Slow motion runs this fraction of real time
+const SLOW_MOTION: u32 = 4;
+
+pub struct Clock {
+    pub instructions_per_frame: usize,
+    pub slow_motion: bool,
+    // Real time not yet turned into frames
+    pending: Duration,
+}
+
+impl Clock {
+    pub fn new(platform: Platform) -> Self {
+        Clock {
+            instructions_per_frame: platform.instructions_per_frame(),
+            slow_motion: false,
+            pending: Duration::ZERO,
+        }
+    }
+
+    /// How many frames to run after `elapsed` real time passed
+    pub fn frames(&mut self, elapsed: Duration) -> u32 {
+        self.pending += if self.slow_motion { elapsed / SLOW_MOTION } else { elapsed };
+        let mut frames = 0;
+        while self.pending >= FRAME {
+            self.pending -= FRAME;
+            frames += 1;
+        }
+        if frames > MAX_CATCH_UP {
+            self.pending = Duration::ZERO;
+            frames = MAX_CATCH_UP;
+        }
+        frames
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.instructions_per_frame, 1..=2000)
+                .logarithmic(true)
+                .text("instructions/frame"),
+        );
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.slow_motion, "Slow motion");
+            ui.label("Hold Tab to fast-forward");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_follow_real_time() {
+        let mut clock = Clock::new(Platform::Vip);
+        assert_eq!(clock.frames(FRAME / 2), 0);
+        assert_eq!(clock.frames(FRAME / 2), 1);
+        assert_eq!(clock.frames(FRAME * 3), 3);
+        // Stalls are not caught up all at once
+        assert_eq!(clock.frames(FRAME * 100), MAX_CATCH_UP);
+        assert_eq!(clock.frames(Duration::ZERO), 0);
+
+        clock.slow_motion = true;
+        assert_eq!(clock.frames(FRAME * 2), 0);
+        assert_eq!(clock.frames(FRAME * 2), 1);
+    }
+}
@@ -0,0 +1,94 @@
+// Starts and stops execution traces from the GUI
+
+use ggez_egui::egui;
+use ultra8::trace::{InstructionClass, TraceFilter};
+use ultra8::{Cpu, TraceFormat, Tracer};
+
+use super::parse_addr;
+
+pub struct TraceWindow {
+    pub open: bool,
+    path_input: String,
+    format: TraceFormat,
+    start_input: String,
+    end_input: String,
+    classes: Vec<(InstructionClass, bool)>,
+    status: String,
+}
+
+impl TraceWindow {
+    pub fn new() -> Self {
+        TraceWindow {
+            open: false,
+            path_input: "trace.txt".to_owned(),
+            format: TraceFormat::Text,
+            start_input: String::new(),
+            end_input: String::new(),
+            classes: InstructionClass::ALL.iter().map(|&class| (class, true)).collect(),
+            status: String::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, cpu: &mut Cpu) {
+        let mut open = self.open;
+        egui::Window::new("Trace").open(&mut open).show(ctx, |ui| {
+            let tracing = cpu.tracing();
+            ui.add_enabled_ui(!tracing, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("File");
+                    ui.text_edit_singleline(&mut self.path_input);
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.format, TraceFormat::Text, "Text");
+                    ui.radio_value(&mut self.format, TraceFormat::Binary, "Binary");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Addresses");
+                    ui.add(egui::TextEdit::singleline(&mut self.start_input).desired_width(50.0));
+                    ui.label("to");
+                    ui.add(egui::TextEdit::singleline(&mut self.end_input).desired_width(50.0));
+                });
+                ui.horizontal_wrapped(|ui| {
+                    for (class, enabled) in self.classes.iter_mut() {
+                        ui.checkbox(enabled, class.name());
+                    }
+                });
+            });
+
+            if tracing {
+                if ui.button("Stop").clicked() {
+                    if let Some(tracer) = cpu.set_tracer(None) {
+                        self.status = match tracer.finish() {
+                            Ok(()) => format!("Trace written to {}", self.path_input),
+                            Err(e) => format!("Trace failed: {}", e),
+                        };
+                    }
+                }
+            } else if ui.button("Start").clicked() {
+                match Tracer::to_file(&self.path_input, self.format) {
+                    Ok(tracer) => {
+                        cpu.set_tracer(Some(tracer.with_filter(self.filter())));
+                        self.status = "Tracing".to_owned();
+                    }
+                    Err(e) => self.status = format!("Could not create {}: {}", self.path_input, e),
+                }
+            }
+            ui.label(&self.status);
+        });
+        self.open = open;
+    }
+
+    fn filter(&self) -> TraceFilter {
+        // Leaving the start empty traces every address
+        let range = parse_addr(&self.start_input).map(|start| {
+            let end = parse_addr(&self.end_input).unwrap_or(0xFFFF);
+            start..=end
+        });
+        let classes = if self.classes.iter().all(|&(_, enabled)| enabled) {
+            None
+        } else {
+            Some(self.classes.iter().filter(|&&(_, enabled)| enabled).map(|&(class, _)| class).collect())
+        };
+        TraceFilter { range, classes }
+    }
+}
@@ -0,0 +1,66 @@
+// Placement of the emulator display in a window of any size
+//
+// The display is fitted as if it was always hires and lores frames are drawn with twice the
+// scale, so switching resolution never changes the size of the picture. Space left over on the
+// sides or at the top and bottom is letterboxed.
+
+use serde::{Deserialize, Serialize};
+use ultra8::cpu::{HIRES_HEIGHT, HIRES_WIDTH};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scaling {
+    /// Whole multiples of the resolution only, every pixel has the same size
+    #[default]
+    Integer,
+    /// As large as fits, keeping the 2:1 aspect ratio
+    Aspect,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 2] = [Scaling::Integer, Scaling::Aspect];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    /// Window pixels per Chip8 pixel of the current resolution
+    pub scale: f32,
+}
+
+impl Viewport {
+    /// Centres a display `width` pixels wide in a `window` sized area
+    pub fn fit(window: (f32, f32), width: usize, scaling: Scaling) -> Self {
+        let (window_width, window_height) = window;
+        let mut scale = (window_width / HIRES_WIDTH as f32).min(window_height / HIRES_HEIGHT as f32);
+        if scaling == Scaling::Integer {
+            // Too small a window still shows the display, cut off
+            scale = scale.floor().max(1.0);
+        }
+        Viewport {
+            x: ((window_width - HIRES_WIDTH as f32 * scale) / 2.0).floor(),
+            y: ((window_height - HIRES_HEIGHT as f32 * scale) / 2.0).floor(),
+            scale: scale * HIRES_WIDTH as f32 / width.max(1) as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_scaling_letterboxes() {
+        let hires = Viewport::fit((800.0, 550.0), 128, Scaling::Integer);
+        assert_eq!(hires, Viewport { x: 16.0, y: 83.0, scale: 6.0 });
+        // Lores fills the same area with pixels twice as big
+        let lores = Viewport::fit((800.0, 550.0), 64, Scaling::Integer);
+        assert_eq!(lores, Viewport { x: 16.0, y: 83.0, scale: 12.0 });
+    }
+
+    #[test]
+    fn aspect_scaling_fills_one_side() {
+        let viewport = Viewport::fit((800.0, 600.0), 128, Scaling::Aspect);
+        assert_eq!(viewport, Viewport { x: 0.0, y: 100.0, scale: 6.25 });
+    }
+}
@@ -0,0 +1,27 @@
+//! Core of the Ultra8 CHIP-8, SUPER-CHIP and XO-CHIP interpreter.
+//!
+//! The core has no frontend dependencies, frontends drive a [`Cpu`] by calling
+//! [`Cpu::cycle`] and [`Cpu::tick_timers`] and reading back the framebuffer.
+
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod coverage;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod filter;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+pub mod palette;
+pub mod profile;
+pub mod quirks;
+pub mod trace;
+
+pub use coverage::Coverage;
+pub use cpu::{AccessKind, Cpu, Fault, MemAccess, Registers, RomTooLarge, VideoMode};
+pub use debugger::{Debugger, StopReason, WatchAccess, WatchEvent, WatchHit, Watchpoint};
+pub use filter::DisplayFilter;
+pub use palette::Palette;
+pub use profile::Profiler;
+pub use quirks::{Platform, Quirks};
+pub use trace::{TraceFormat, Tracer};
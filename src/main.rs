@@ -1,22 +1,42 @@
-use ggez::conf::{WindowSetup, NumSamples, WindowMode};
+use ggez::conf::{FullscreenType, WindowSetup, NumSamples, WindowMode};
 use ggez::{Context, ContextBuilder, GameResult};
 use ggez::graphics::{self, Color};
-use ggez::event::{self, EventHandler, KeyCode, KeyMods};
+use ggez::event::{self, Axis, Button, EventHandler, GamepadId, KeyCode, KeyMods};
+use anyhow::Context as _;
 use ggez_egui::{egui, EguiBackend};
 use ggez::input::mouse::MouseButton;
-mod cpu;
-use cpu::Cpu;
+mod gui;
+use gui::browser::RomBrowser;
+use gui::config::{self, Config, CONFIG_FILE};
+use gui::disassembly::DisassemblyView;
+use gui::events::FileDropHandler;
+use gui::display::DisplaySettings;
+use gui::inspector::Inspector;
+use gui::keypad::KeypadSettings;
+use gui::memory::MemoryView;
+use gui::profiler::ProfilerWindow;
+use gui::speed::{Clock, FRAME};
+use gui::trace::TraceWindow;
+use gui::viewport::Viewport;
+use gui::parse_addr;
+use ultra8::capture::{GifRecorder, Image};
+use ultra8::{Cpu, Debugger, DisplayFilter, Palette, Platform, StopReason, WatchAccess, WatchEvent, Watchpoint};
 
-// Offset of the game window from the left side of the screen
-const X_OFFSET: usize = 100;
-const Y_OFFSET: usize = 100;
+const SCREENSHOT_DIR: &str = "screenshots";
+// GIFs are recorded smaller than the window to keep them small
+const GIF_SCALE: usize = 4;
+// How often watch mode looks at the rom's modification time
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 fn main() {
     // Make a Context.
-    let (mut ctx, event_loop) = ContextBuilder::new("my_game", "Cool Game Author")
+    let (ctx, event_loop) = ContextBuilder::new("my_game", "Cool Game Author")
         .window_mode(WindowMode {
-            width: 800.0,
-            height: 550.0,
+            width: 1024.0,
+            height: 640.0,
+            resizable: true,
+            min_width: 256.0,
+            min_height: 160.0,
             ..Default::default()
         })
         .window_setup(WindowSetup {
@@ -29,151 +49,599 @@ fn main() {
         .build()
         .expect("aieee, could not create ggez context!");
 
-    // Create an instance of your event handler.
-    // Usually, you should provide it with the Context object to
-    // use when setting your game up.
-    let my_game = MyGame::new(&mut ctx);
+    let config = Config::load(CONFIG_FILE).unwrap_or_else(|e| {
+        println!("{:#}, using the default settings", e);
+        Config::default()
+    });
+    // Without a rom on the command line the browser picks one
+    let my_game = MyGame::new(config, env::args().nth(1));
 
     // Run!
-    event::run(ctx, event_loop, my_game);
+    gui::events::run(ctx, event_loop, my_game);
 }
 
 struct MyGame {
     chip8: Cpu,
     egui_backend: EguiBackend,
+    config: Config,
+    // File name of the rom without its extension, used for per-rom settings
+    rom: String,
+    // Path of the loaded rom and its modification time when it was loaded
+    rom_path: Option<PathBuf>,
+    rom_modified: Option<SystemTime>,
+    // Watch mode reloads the rom when the file changes, optionally keeping the registers
+    watch: bool,
+    keep_registers: bool,
+    last_watch: Instant,
+    // Gamepad buttons held down
+    pad_buttons: Vec<Button>,
+    // D-pad direction the left stick is pushed in on each axis, kept apart so stick movement
+    // never releases a d-pad button that is physically held
+    stick_buttons: Vec<(Axis, Button)>,
 
-    chip8_running: bool,
+    debugger: Debugger,
+    // Last reason the debugger stopped, shown in the control window
+    status: String,
+    breakpoint_input: String,
+    run_to_input: String,
+    watch_start_input: String,
+    watch_end_input: String,
+    watch_access: WatchAccess,
+    watch_register: u8,
+    memory_view: MemoryView,
+    disassembly_view: DisassemblyView,
+    inspector: Inspector,
+    trace_window: TraceWindow,
+    profiler_window: ProfilerWindow,
+    keypad_settings: KeypadSettings,
+    display_settings: DisplaySettings,
+    rom_browser: RomBrowser,
 
-    update_once: u16,
+    platform: Platform,
+    clock: Clock,
+    // Frames run with frame advance since the last pause
+    frames_advanced: u32,
+
+    palette: Palette,
+    filter: DisplayFilter,
+    // RGBA copy of the framebuffer and the texture made from it
+    pixels: Vec<u8>,
+    screen: Option<graphics::Image>,
+    // Where the display was last drawn, and whether the window is fullscreen right now
+    viewport: Viewport,
+    fullscreen: bool,
+    // GIF being recorded and its path
+    recorder: Option<(GifRecorder<BufWriter<File>>, PathBuf)>,
 }
 
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 impl MyGame {
-    pub fn new(_ctx: &mut Context) -> MyGame {
-        // Load/create resources such as images here.
-        MyGame {
-            chip8: {
-                let args: Vec<String> = env::args().collect();
-                if args.len() == 1 {
-                    panic!("No argument specified!");
-                }
-                let mut file = File::open(&args[1]).expect("File failed to read!");
-                let mut buf: Vec<u8> = Vec::new();
-                file.read_to_end(&mut buf);
-            
-                let mut c8 = cpu::Cpu::init();
-                c8.load(&buf);
-
-                c8
-            },
+    pub fn new(config: Config, rom: Option<String>) -> MyGame {
+        let palette = config.display.palette();
+        let filter = config.display.filter();
+        let mut game = MyGame {
+            chip8: Cpu::init(),
+            config,
+            rom: String::new(),
+            rom_path: None,
+            rom_modified: None,
+            watch: false,
+            keep_registers: false,
+            last_watch: Instant::now(),
+            pad_buttons: Vec::new(),
+            stick_buttons: Vec::new(),
 
-            update_once: 0,
+            platform: Platform::Vip,
+            clock: Clock::new(Platform::Vip),
+            frames_advanced: 0,
+            palette,
+            filter,
+            pixels: Vec::new(),
+            screen: None,
+            viewport: Viewport { x: 0.0, y: 0.0, scale: 1.0 },
+            fullscreen: false,
+            recorder: None,
 
             egui_backend: EguiBackend::default(),
-            chip8_running: true,
+            debugger: Debugger::new(),
+            status: String::new(),
+            breakpoint_input: String::new(),
+            run_to_input: String::new(),
+            watch_start_input: String::new(),
+            watch_end_input: String::new(),
+            watch_access: WatchAccess::Write,
+            watch_register: 0,
+            memory_view: MemoryView::new(),
+            disassembly_view: DisassemblyView::new(),
+            inspector: Inspector::new(),
+            trace_window: TraceWindow::new(),
+            profiler_window: ProfilerWindow::new(),
+            keypad_settings: KeypadSettings::new(),
+            display_settings: DisplaySettings::new(),
+            rom_browser: RomBrowser::new(),
+        };
+
+        match rom {
+            Some(path) => {
+                if let Err(e) = game.load_rom(Path::new(&path)) {
+                    game.status = format!("{:#}", e);
+                    game.rom_browser.open = true;
+                }
+            }
+            None => game.rom_browser.open = true,
+        }
+        game
+    }
+
+    // Replaces the machine with a fresh one running `path`, on the platform the rom looks written for
+    fn load_rom(&mut self, path: &Path) -> anyhow::Result<()> {
+        let buf = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut chip8 = Cpu::init();
+        chip8.load(&buf).with_context(|| format!("Failed to load {}", path.display()))?;
+        if let Some(tracer) = self.chip8.set_tracer(None) {
+            let _ = tracer.finish();
+        }
+        let stopped = self.stopped_by_program();
+        self.chip8 = chip8;
+        if stopped {
+            self.debugger.resume(&self.chip8);
+        }
+        self.rom = config::rom_name(&path.to_string_lossy());
+        self.rom_path = Some(path.to_owned());
+        self.rom_modified = modified(path);
+        self.platform = Platform::detect(&buf);
+        self.chip8.set_quirks(self.platform.quirks());
+        self.clock = Clock::new(self.platform);
+        self.filter = self.config.display.filter();
+        self.screen = None;
+        self.frames_advanced = 0;
+        self.status = format!("Loaded {} ({})", path.display(), self.platform.name());
+        Ok(())
+    }
+
+    // Runs one 60hz frame of instructions and ticks the timers, false if the debugger stopped it
+    fn run_frame(&mut self) -> bool {
+        // Empty memory is not a program, nothing runs until the browser loads one
+        if self.rom_path.is_none() || self.debugger.paused() || self.stopped_by_program() {
+            return false;
+        }
+        if let Some(reason) = self.debugger.run(&mut self.chip8, self.clock.instructions_per_frame) {
+            self.stopped(reason);
+            return false;
+        }
+        self.chip8.tick_timers();
+        self.end_frame();
+        true
+    }
+
+    fn end_frame(&mut self) {
+        self.filter.end_frame(&self.chip8);
+        if self.filter.enabled() {
+            // Filtered output changes every frame even if nothing was drawn
+            self.screen = None;
+        }
+        if let Some((recorder, _)) = &mut self.recorder {
+            if let Err(e) = recorder.frame(&self.chip8) {
+                self.recorder = None;
+                self.status = format!("Recording failed: {}", e);
+            }
+        }
+    }
+
+    // Saves what is on screen to screenshots/, at native resolution and at the window's scale
+    fn screenshot(&mut self) {
+        let (width, height) = (self.chip8.width(), self.chip8.height());
+        let mut image = Image { width, height, rgba: vec![0; width * height * 4] };
+        self.filter.render_rgba(&self.chip8, &self.palette, &mut image.rgba);
+
+        let dir = Path::new(SCREENSHOT_DIR);
+        let n = (1..).find(|n| !dir.join(format!("{}-{}.png", self.rom, n)).exists()).unwrap_or(1);
+        let native = dir.join(format!("{}-{}.png", self.rom, n));
+        let scale = (self.viewport.scale as usize).max(1);
+        let scaled = dir.join(format!("{}-{}-x{}.png", self.rom, n, scale));
+        let result = fs::create_dir_all(dir)
+            .and_then(|_| image.save_png(&native))
+            .and_then(|_| image.scaled(scale).save_png(&scaled));
+        self.status = match result {
+            Ok(()) => format!("Saved {}", native.display()),
+            Err(e) => format!("Screenshot failed: {}", e),
+        };
+    }
+
+    // A soft reset restarts the program with memory as it is, a hard one reloads the rom and fonts
+    fn reset(&mut self, hard: bool) {
+        let stopped = self.stopped_by_program();
+        if hard {
+            self.chip8.hard_reset();
+        } else {
+            self.chip8.soft_reset();
+        }
+        if stopped {
+            self.debugger.resume(&self.chip8);
+        }
+        self.filter = self.config.display.filter();
+        self.screen = None;
+        self.frames_advanced = 0;
+        self.status = if hard { "Hard reset" } else { "Reset" }.to_owned();
+    }
+
+    // A halt or fault pauses the debugger, starting over should run again without pressing Continue
+    fn stopped_by_program(&self) -> bool {
+        self.chip8.halted() || self.chip8.fault().is_some()
+    }
+
+    // Reloads the rom if watch mode is on and the file changed since it was loaded
+    fn reload_if_changed(&mut self) {
+        if !self.watch || self.last_watch.elapsed() < WATCH_INTERVAL {
+            return;
+        }
+        self.last_watch = Instant::now();
+        let Some(path) = self.rom_path.clone() else {
+            return;
+        };
+        if modified(&path) == self.rom_modified {
+            return;
+        }
+        let registers = self.chip8.registers();
+        let platform = self.platform;
+        match self.load_rom(&path) {
+            Ok(()) if self.keep_registers => {
+                // Stay on the platform the previous run used, its code may depend on it
+                self.platform = platform;
+                self.chip8.set_quirks(platform.quirks());
+                self.clock = Clock::new(platform);
+                self.chip8.set_registers(&registers);
+                self.status = format!("Reloaded {}, registers kept", path.display());
+            }
+            Ok(()) => self.status = format!("Reloaded {}", path.display()),
+            Err(e) => {
+                // Don't retry a broken file until it changes again
+                self.rom_modified = modified(&path);
+                self.status = format!("{:#}", e);
+            }
+        }
+    }
+
+    // Starts recording to screenshots/<rom>-<n>.gif, or finishes the recording
+    fn toggle_recording(&mut self) {
+        if let Some((recorder, path)) = self.recorder.take() {
+            let frames = recorder.frames();
+            self.status = match recorder.finish() {
+                Ok(_) => format!("Saved {} ({} frames)", path.display(), frames),
+                Err(e) => format!("Recording failed: {}", e),
+            };
+            return;
+        }
+        let dir = Path::new(SCREENSHOT_DIR);
+        let n = (1..).find(|n| !dir.join(format!("{}-{}.gif", self.rom, n)).exists()).unwrap_or(1);
+        let path = dir.join(format!("{}-{}.gif", self.rom, n));
+        let result = fs::create_dir_all(dir).and_then(|_| GifRecorder::create(&path, &self.palette, GIF_SCALE));
+        self.status = match result {
+            Ok(recorder) => {
+                let status = format!("Recording {}", path.display());
+                self.recorder = Some((recorder, path));
+                status
+            }
+            Err(e) => format!("Recording failed: {}", e),
+        };
+    }
+
+    fn toggle_pause(&mut self) {
+        self.frames_advanced = 0;
+        if self.debugger.paused() {
+            self.debugger.resume(&self.chip8);
+            self.status.clear();
+        } else {
+            self.debugger.pause();
+            self.status = format!("Paused at {:#05X}", self.chip8.pc());
+        }
+    }
+
+    // Pauses and runs exactly one frame, the timers only tick if the whole frame ran
+    fn advance_frame(&mut self) {
+        if self.rom_path.is_none() {
+            return;
+        }
+        match self.debugger.advance(&mut self.chip8, self.clock.instructions_per_frame) {
+            Some(reason) => self.stopped(reason),
+            None => {
+                self.chip8.tick_timers();
+                self.end_frame();
+                self.frames_advanced += 1;
+                self.status = format!("Advanced {} frames, paused at {:#05X}", self.frames_advanced, self.chip8.pc());
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: StopReason) {
+        self.status = match reason {
+            StopReason::Breakpoint(addr) => format!("Breakpoint at {:#05X}", addr),
+            StopReason::Step => format!("Paused at {:#05X}", self.chip8.pc()),
+            // 00FD stops the program, not the interpreter, a reset or another rom starts over
+            StopReason::Halted => "Halted, F2 to reset".to_owned(),
+            StopReason::Fault(fault) => fault.to_string(),
+            StopReason::Watchpoint(hit) => {
+                let event = match hit.event {
+                    WatchEvent::Read(addr) => format!("read {:#05X}", addr),
+                    WatchEvent::Write(addr) => format!("wrote {:#05X}", addr),
+                    WatchEvent::Changed { old, new } => format!("changed {:?} {:#X} -> {:#X}", hit.watchpoint, old, new),
+                };
+                format!("Watchpoint: {:04X} at {:#05X} {}", hit.opcode, hit.pc, event)
+            }
+        };
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl FileDropHandler for MyGame {
+    fn file_dropped(&mut self, _ctx: &mut Context, path: PathBuf) {
+        if let Err(e) = self.load_rom(&path) {
+            self.status = format!("{:#}", e);
         }
     }
 }
 
-const SCALE: i32 = 7;
 impl EventHandler for MyGame {
     fn update(&mut self, _ctx: &mut Context) -> GameResult<()> {
         let egui_ctx = self.egui_backend.ctx();
 		egui::Window::new("Chip8 Control").show(&egui_ctx, |ui| {
             ui.label(format!("{}", ggez::timer::fps(_ctx) as usize));
-            ui.checkbox(&mut self.chip8_running, "Running");
-            if ui.button("Cycle").clicked() {
-                self.chip8.cycle();
-                // todo!();
+            let mut running = !self.debugger.paused();
+            if ui.checkbox(&mut running, "Running (F5)").changed() {
+                self.toggle_pause();
             }
-            if ui.button("Dump Ram").clicked() {
-                // println!("{}", self.chip8.ra)
-                todo!();
+            ui.add_enabled_ui(self.rom_path.is_some(), |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Frame").on_hover_text("Run one frame and tick the timers (F6)").clicked() {
+                        self.advance_frame();
+                    }
+                    if ui.button("Cycle").clicked() {
+                        let reason = self.debugger.step_into(&mut self.chip8);
+                        self.stopped(reason);
+                    }
+                    if ui.button("Step Over").clicked() {
+                        if let Some(reason) = self.debugger.step_over(&mut self.chip8) {
+                            self.stopped(reason);
+                        }
+                    }
+                    if ui.button("Step Out").clicked() {
+                        self.debugger.step_out(&self.chip8);
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Reset").on_hover_text("Restart the program, memory is kept (F2)").clicked() {
+                    self.reset(false);
+                }
+                if ui.button("Hard Reset").on_hover_text("Reload the rom and fonts (Shift+F2)").clicked() {
+                    self.reset(true);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.run_to_input);
+                if ui.button("Run to").clicked() {
+                    if let Some(addr) = parse_addr(&self.run_to_input) {
+                        self.debugger.run_to(&self.chip8, addr);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.watch, "Reload on change");
+                ui.add_enabled(self.watch, egui::Checkbox::new(&mut self.keep_registers, "Keep registers"));
+            });
+            let mut recording = self.recorder.is_some();
+            if ui.checkbox(&mut recording, "Record GIF (F11)").changed() {
+                self.toggle_recording();
             }
+            ui.label(&self.status);
+            if self.chip8.waiting_for_key() {
+                let x = (self.chip8.read_word(self.chip8.pc()) >> 8) & 0xF;
+                ui.colored_label(egui::Color32::YELLOW, format!("Waiting for a key (FX0A into V{:X})", x));
+            }
+            let platform = self.platform;
+            egui::ComboBox::from_label("Platform").selected_text(platform.name()).show_ui(ui, |ui| {
+                for p in Platform::ALL {
+                    ui.selectable_value(&mut self.platform, p, p.name());
+                }
+            });
+            if self.platform != platform {
+                self.chip8.set_quirks(self.platform.quirks());
+                self.clock = Clock::new(self.platform);
+            }
+            self.clock.show(ui);
+            let mut quirks = self.chip8.quirks();
+            let key_release = ui.checkbox(&mut quirks.key_release, "FX0A waits for key release");
+            if key_release.on_hover_text("Picking a platform sets this to the platform's behaviour").changed() {
+                self.chip8.set_quirks(quirks);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Roms").clicked() {
+                    self.rom_browser.open = !self.rom_browser.open;
+                }
+                if ui.button("Dump Ram").clicked() {
+                    self.memory_view.open = !self.memory_view.open;
+                }
+                if ui.button("Disassembly").clicked() {
+                    self.disassembly_view.open = !self.disassembly_view.open;
+                }
+                if ui.button("Registers").clicked() {
+                    self.inspector.open = !self.inspector.open;
+                }
+                if ui.button("Trace").clicked() {
+                    self.trace_window.open = !self.trace_window.open;
+                }
+                if ui.button("Profiler").clicked() {
+                    self.profiler_window.open = !self.profiler_window.open;
+                }
+                if ui.button("Keypad").clicked() {
+                    self.keypad_settings.open = !self.keypad_settings.open;
+                }
+                if ui.button("Display").clicked() {
+                    self.display_settings.open = !self.display_settings.open;
+                }
+            });
             
 		});
-        egui::Window::new("CHIP8 Registers").show(&egui_ctx, |ui|{
-            ui.label(format!("{:#?}", self.chip8.v));
-        }
-        );
-
-        if self.update_once >= 16 {
-            self.update_once = 0;
-            if self.chip8.dt != 0 {
-                self.chip8.dt -= 1;
+        egui::Window::new("Breakpoints").show(&egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.breakpoint_input);
+                if ui.button("Add").clicked() {
+                    if let Some(addr) = parse_addr(&self.breakpoint_input) {
+                        self.debugger.add_breakpoint(addr);
+                        self.breakpoint_input.clear();
+                    }
+                }
+            });
+            let mut remove = None;
+            for addr in self.debugger.breakpoints() {
+                ui.horizontal(|ui| {
+                    ui.monospace(format!("{:#05X}", addr));
+                    if ui.button("Remove").clicked() {
+                        remove = Some(addr);
+                    }
+                });
+            }
+            if let Some(addr) = remove {
+                self.debugger.remove_breakpoint(addr);
+            }
+        });
+        egui::Window::new("Watchpoints").show(&egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Memory");
+                ui.text_edit_singleline(&mut self.watch_start_input);
+                ui.label("to");
+                ui.text_edit_singleline(&mut self.watch_end_input);
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.watch_access, WatchAccess::Read, "Read");
+                ui.radio_value(&mut self.watch_access, WatchAccess::Write, "Write");
+                ui.radio_value(&mut self.watch_access, WatchAccess::ReadWrite, "Both");
+                if ui.button("Watch").clicked() {
+                    if let Some(start) = parse_addr(&self.watch_start_input) {
+                        // A single address is watched when no end is given
+                        let end = parse_addr(&self.watch_end_input).unwrap_or(start).max(start);
+                        self.debugger.add_watchpoint(Watchpoint::Memory { start, end, access: self.watch_access });
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.watch_register).clamp_range(0..=15).prefix("V"));
+                if ui.button("Watch V").clicked() {
+                    self.debugger.add_watchpoint(Watchpoint::Register(self.watch_register));
+                }
+                if ui.button("Watch I").clicked() {
+                    self.debugger.add_watchpoint(Watchpoint::Index);
+                }
+                if ui.button("Watch DT").clicked() {
+                    self.debugger.add_watchpoint(Watchpoint::DelayTimer);
+                }
+            });
+            ui.separator();
+            let mut remove = None;
+            for &watchpoint in self.debugger.watchpoints() {
+                ui.horizontal(|ui| {
+                    ui.monospace(match watchpoint {
+                        Watchpoint::Memory { start, end, access } => format!("{:#05X}-{:#05X} {:?}", start, end, access),
+                        Watchpoint::Register(reg) => format!("V{:X}", reg),
+                        Watchpoint::Index => "I".to_owned(),
+                        Watchpoint::DelayTimer => "DT".to_owned(),
+                    });
+                    if ui.button("Remove").clicked() {
+                        remove = Some(watchpoint);
+                    }
+                });
+            }
+            if let Some(watchpoint) = remove {
+                self.debugger.remove_watchpoint(watchpoint);
             }
+        });
+        if self.memory_view.open {
+            self.memory_view.show(&egui_ctx, &mut self.chip8, self.debugger.paused());
         }
-        self.update_once += 1;
-
-        self.chip8.dt -= 1;
-        if self.chip8_running {
-            self.chip8.cycle();
+        if self.disassembly_view.open {
+            self.disassembly_view.show(&egui_ctx, &self.chip8, &mut self.debugger);
         }
-        
-        if ggez::input::keyboard::is_key_pressed(_ctx, KeyCode::Q) {
-            self.chip8.set_key(4,1);
-        } else {
-            self.chip8.set_key(4,0);
+        if self.inspector.open {
+            self.inspector.show(&egui_ctx, &mut self.chip8, self.debugger.paused());
+        }
+        if self.trace_window.open {
+            self.trace_window.show(&egui_ctx, &mut self.chip8);
+        }
+        if self.profiler_window.open {
+            self.profiler_window.show(&egui_ctx, &mut self.chip8);
+        }
+        if self.keypad_settings.open {
+            self.keypad_settings.show(&egui_ctx, &mut self.config, &self.rom);
+        }
+        if self.display_settings.open && self.display_settings.show(&egui_ctx, &mut self.config, &mut self.palette, &mut self.filter) {
+            // Re-render with the new colours and filters
+            self.screen = None;
+        }
+        if self.rom_browser.open {
+            if let Some(path) = self.rom_browser.show(&egui_ctx, &mut self.config) {
+                if let Err(e) = self.load_rom(&path) {
+                    self.status = format!("{:#}", e);
+                }
+            }
         }
+        if self.config.display.fullscreen != self.fullscreen {
+            self.fullscreen = self.config.display.fullscreen;
+            let mode = if self.fullscreen { FullscreenType::Desktop } else { FullscreenType::Windowed };
+            graphics::set_fullscreen(_ctx, mode)?;
+        }
+
+        self.reload_if_changed();
 
-        if ggez::input::keyboard::is_key_pressed(_ctx, KeyCode::E) {
-            self.chip8.set_key(6,1);
+        if ggez::input::keyboard::is_key_pressed(_ctx, KeyCode::Tab) {
+            // Fast-forward runs as many frames as fit in one frame of real time
+            let start = Instant::now();
+            while start.elapsed() < FRAME && self.run_frame() {}
         } else {
-            self.chip8.set_key(6,0);
+            for _ in 0..self.clock.frames(ggez::timer::delta(_ctx)) {
+                self.run_frame();
+            }
         }
 
-        if ggez::input::keyboard::is_key_pressed(_ctx, KeyCode::W) {
-            self.chip8.set_key(5,1);
-        } else {
-            self.chip8.set_key(5,0);
+        let keymap = self.config.keyboard.keymap(&self.rom);
+        let buttons: Vec<Button> = self.pad_buttons.iter().copied().chain(self.stick_buttons.iter().map(|&(_, button)| button)).collect();
+        for (key, &code) in keymap.0.iter().enumerate() {
+            let pressed = ggez::input::keyboard::is_key_pressed(_ctx, code)
+                || buttons.iter().any(|&button| self.config.gamepad.key(&self.rom, button) == Some(key as u8));
+            self.chip8.set_key(key as u8, pressed as u8);
         }
 
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        
         graphics::clear(ctx, Color::BLACK);
-        
-        // Draw code here...
-        
 
-        // let gfx = c8.get_graphics();
-        // if c8.draw {
-        //     for y in 0..32 {
-        //         for x in 0..64 {
-        //             if gfx[(x + (y * 64)) as usize] == 1 {
-        //                 let pix_y = y * SCALE;
-        //                 let pix_x = x * SCALE;
-        //                 d.draw_rectangle(pix_x, pix_y, SCALE, SCALE, Color::RED);
-
-        //             } else {
-        //                 let pix_y = y * SCALE;
-        //                 let pix_x = x * SCALE;
-        //                 d.draw_rectangle(pix_x, pix_y, SCALE, SCALE, Color::BLACK);
-        //             }
-        //         }
-        //     }
-        // }
-
-        let framebuffer = self.chip8.get_graphics();
-        if self.chip8.draw {
-            for y in 0..32 {
-                for x in 0..64 {
-                    if framebuffer[x + (y * 64) as usize] == 1 {
-                        let pix_y = (y * SCALE) + Y_OFFSET as i32;
-                        let pix_x = (x * (SCALE as usize)) + X_OFFSET;
-                        let pixel = 
-                        graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), graphics::Rect::new(pix_x as f32, pix_y as f32, SCALE as f32, SCALE as f32), graphics::Color::WHITE).unwrap();
-                        graphics::draw(ctx, &pixel, graphics::DrawParam::default()).expect("ASd");
-                        // println!("{}, {}", pix_x, pix_y);
-                        
-                    }
-                }
-            }
+        // The texture is only rebuilt after the framebuffer or its resolution changed
+        let resized = self.screen.as_ref().is_some_and(|screen| screen.width() as usize != self.chip8.width());
+        if self.chip8.draw || self.screen.is_none() || resized {
+            let (width, height) = (self.chip8.width(), self.chip8.height());
+            self.pixels.resize(width * height * 4, 0);
+            self.filter.render_rgba(&self.chip8, &self.palette, &mut self.pixels);
+            let mut image = graphics::Image::from_rgba8(ctx, width as u16, height as u16, &self.pixels)?;
+            image.set_filter(graphics::FilterMode::Nearest);
+            self.screen = Some(image);
+            self.chip8.draw = false;
+        }
+        let window = graphics::screen_coordinates(ctx);
+        self.viewport = Viewport::fit((window.w, window.h), self.chip8.width(), self.config.display.scaling);
+        if let Some(screen) = &self.screen {
+            let param = graphics::DrawParam::default()
+                .dest([self.viewport.x, self.viewport.y])
+                .scale([self.viewport.scale, self.viewport.scale]);
+            graphics::draw(ctx, screen, param)?;
         }
         graphics::draw(ctx, &self.egui_backend, ([0.0, 0.0],))?;
         graphics::present(ctx).unwrap();
@@ -193,4 +661,93 @@ impl EventHandler for MyGame {
 	fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
 		self.egui_backend.input.mouse_motion_event(x, y);
 	}
-}
\ No newline at end of file
+
+	fn mouse_wheel_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
+		self.egui_backend.input.mouse_wheel_event(x, y);
+	}
+
+	fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
+		self.egui_backend.input.key_down_event(keycode, keymods);
+		if self.keypad_settings.capture(keycode, &mut self.config, &self.rom) {
+			return;
+		}
+		match keycode {
+			KeyCode::Escape => event::quit(ctx),
+			KeyCode::F2 if !repeat => self.reset(keymods.contains(KeyMods::SHIFT)),
+			KeyCode::F5 if !repeat => self.toggle_pause(),
+			KeyCode::F6 => self.advance_frame(),
+			KeyCode::Return if keymods.contains(KeyMods::ALT) && !repeat => {
+				self.config.display.fullscreen = !self.config.display.fullscreen;
+			}
+			KeyCode::F11 if !repeat => self.toggle_recording(),
+			KeyCode::F12 if !repeat => self.screenshot(),
+			_ => {}
+		}
+	}
+
+	fn gamepad_button_down_event(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+		if !self.pad_buttons.contains(&button) {
+			self.pad_buttons.push(button);
+		}
+	}
+
+	fn gamepad_button_up_event(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+		self.pad_buttons.retain(|&b| b != button);
+	}
+
+	fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+		if let Some((negative, positive)) = config::stick_buttons(axis) {
+			self.stick_buttons.retain(|&(a, _)| a != axis);
+			if value < -0.5 {
+				self.stick_buttons.push((axis, negative));
+			} else if value > 0.5 {
+				self.stick_buttons.push((axis, positive));
+			}
+		}
+	}
+
+	fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+		// Keep drawing in window pixels instead of stretching the old coordinates
+		let _ = graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height));
+		self.egui_backend.input.resize_event(width, height);
+	}
+
+	fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+		self.egui_backend.input.text_input_event(character);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ultra8::cpu::PROGRAM_START;
+
+    #[test]
+    fn nothing_runs_without_a_rom() {
+        let mut game = MyGame::new(Config::default(), None);
+        assert!(game.rom_browser.open);
+        assert!(!game.run_frame());
+        game.advance_frame();
+        assert_eq!(game.chip8.pc(), PROGRAM_START as u16);
+    }
+
+    #[test]
+    fn reset_runs_a_halted_rom_again() {
+        // LD V1, 1 / EXIT
+        let path = std::env::temp_dir().join(format!("ultra8-halt-{}.ch8", std::process::id()));
+        fs::write(&path, [0x61, 0x01, 0x00, 0xFD]).unwrap();
+        let mut game = MyGame::new(Config::default(), None);
+        game.load_rom(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(!game.run_frame());
+        assert!(game.chip8.halted());
+        assert_eq!(game.chip8.v[1], 1);
+
+        game.reset(false);
+        assert_eq!(game.chip8.v[1], 0);
+        game.run_frame();
+        assert_eq!(game.chip8.v[1], 1);
+        assert!(game.chip8.halted());
+    }
+}
@@ -0,0 +1,115 @@
+// Colours the framebuffer is rendered with
+//
+// Pixels hold one bit per XO-Chip plane, so a palette has a colour for each of the four plane
+// combinations. Plain Chip8 and SCHIP roms only draw to the first plane and use the first two.
+
+/// An RGBA colour
+pub type Rgba = [u8; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+	/// Indexed by pixel value: background, plane 1, plane 2, both planes
+	pub colors: [Rgba; 4],
+}
+
+impl Palette {
+	/// White on black, the XO-Chip planes in Octo's default red and grey
+	pub const CLASSIC: Palette = Palette {
+		colors: [[0x00, 0x00, 0x00, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF], [0xAA, 0x22, 0x22, 0xFF], [0x55, 0x55, 0x55, 0xFF]],
+	};
+
+	/// Dark green on the pale green of old handheld LCDs
+	pub const LCD_GREEN: Palette = Palette {
+		colors: [[0x9B, 0xBC, 0x0F, 0xFF], [0x0F, 0x38, 0x0F, 0xFF], [0x30, 0x62, 0x30, 0xFF], [0x8B, 0xAC, 0x0F, 0xFF]],
+	};
+
+	/// Amber monochrome monitor
+	pub const AMBER: Palette = Palette {
+		colors: [[0x1A, 0x0F, 0x00, 0xFF], [0xFF, 0xB0, 0x00, 0xFF], [0xB3, 0x6B, 0x00, 0xFF], [0xFF, 0xD8, 0x70, 0xFF]],
+	};
+
+	pub const HIGH_CONTRAST: Palette = Palette {
+		colors: [[0x00, 0x00, 0x00, 0xFF], [0xFF, 0xFF, 0x00, 0xFF], [0x00, 0xFF, 0xFF, 0xFF], [0xFF, 0x00, 0xFF, 0xFF]],
+	};
+
+	/// Octo's default XO-Chip colours
+	pub const OCTO: Palette = Palette {
+		colors: [[0x99, 0x66, 0x00, 0xFF], [0xFF, 0xCC, 0x00, 0xFF], [0xFF, 0x66, 0x00, 0xFF], [0x66, 0x22, 0x00, 0xFF]],
+	};
+
+	/// Octo's "hot dog" XO-Chip colours
+	pub const HOT_DOG: Palette = Palette {
+		colors: [[0x00, 0x00, 0x00, 0xFF], [0xFF, 0x00, 0x00, 0xFF], [0xFF, 0xFF, 0x00, 0xFF], [0xFF, 0xFF, 0xFF, 0xFF]],
+	};
+
+	/// The built in palettes and their names
+	pub const PRESETS: [(&'static str, Palette); 6] = [
+		("Classic", Palette::CLASSIC),
+		("LCD green", Palette::LCD_GREEN),
+		("Amber", Palette::AMBER),
+		("High contrast", Palette::HIGH_CONTRAST),
+		("Octo", Palette::OCTO),
+		("Hot dog", Palette::HOT_DOG),
+	];
+
+	pub fn preset(name: &str) -> Option<Palette> {
+		Palette::PRESETS.iter().find(|&&(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, palette)| palette)
+	}
+
+	/// Builds a palette from four `#RRGGBB` colours
+	pub fn from_hex<S: AsRef<str>>(colors: &[S]) -> Option<Palette> {
+		if colors.len() != 4 {
+			return None;
+		}
+		let mut palette = Palette::CLASSIC;
+		for (color, hex) in palette.colors.iter_mut().zip(colors) {
+			*color = parse_hex(hex.as_ref())?;
+		}
+		Some(palette)
+	}
+
+	/// The four colours as `#RRGGBB`
+	pub fn to_hex(&self) -> Vec<String> {
+		self.colors.iter().map(|c| format!("#{:02X}{:02X}{:02X}", c[0], c[1], c[2])).collect()
+	}
+
+	pub fn color(&self, pixel: u8) -> Rgba {
+		self.colors[(pixel & 0x3) as usize]
+	}
+}
+
+impl Default for Palette {
+	fn default() -> Self {
+		Palette::CLASSIC
+	}
+}
+
+// Parses #RRGGBB, the leading # is optional
+fn parse_hex(hex: &str) -> Option<Rgba> {
+	let hex = hex.trim().trim_start_matches('#');
+	if hex.len() != 6 {
+		return None;
+	}
+	let value = u32::from_str_radix(hex, 16).ok()?;
+	Some([(value >> 16) as u8, (value >> 8) as u8, value as u8, 0xFF])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hex_round_trip() {
+		for (_, palette) in Palette::PRESETS {
+			assert_eq!(Palette::from_hex(&palette.to_hex()), Some(palette));
+		}
+		assert_eq!(Palette::from_hex(&["#000000", "fff", "#000000", "#000000"]), None);
+		assert_eq!(Palette::from_hex(&["#000000"]), None);
+	}
+
+	#[test]
+	fn presets_by_name() {
+		assert_eq!(Palette::preset("amber"), Some(Palette::AMBER));
+		assert_eq!(Palette::preset("sepia"), None);
+	}
+}
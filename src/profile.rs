@@ -0,0 +1,169 @@
+// Execution profiler
+//
+// A profiler is attached to a Cpu with `Cpu::set_profiler` and counts how often every address is
+// executed. It also follows 2NNN calls and 00EE returns so instructions can be attributed to the
+// subroutine they ran in, code outside of any call is attributed to the program start.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::cpu::{MEMSIZE, PROGRAM_START};
+use crate::disasm;
+
+/// Time spent in one subroutine, keyed by its 2NNN target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubroutineStats {
+	pub calls: u64,
+	/// Instructions executed in the subroutine itself, not counting nested calls
+	pub instructions: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+	counts: Vec<u64>,
+	total: u64,
+	// Targets of the calls that have not returned yet
+	calls: Vec<u16>,
+	subroutines: BTreeMap<u16, SubroutineStats>,
+}
+
+impl Default for Profiler {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Profiler {
+	pub fn new() -> Self {
+		Profiler {
+			counts: vec![0; MEMSIZE],
+			total: 0,
+			calls: Vec::new(),
+			subroutines: BTreeMap::new(),
+		}
+	}
+
+	// Called by the cpu before executing `opcode` at `pc`
+	pub(crate) fn record(&mut self, pc: u16, opcode: u16) {
+		self.counts[pc as usize] += 1;
+		self.total += 1;
+
+		let current = self.calls.last().copied().unwrap_or(PROGRAM_START as u16);
+		self.subroutines.entry(current).or_default().instructions += 1;
+
+		if opcode & 0xF000 == 0x2000 {
+			let target = opcode & 0x0FFF;
+			self.subroutines.entry(target).or_default().calls += 1;
+			self.calls.push(target);
+		} else if opcode == 0x00EE {
+			self.calls.pop();
+		}
+	}
+
+	/// How many times the instruction at `addr` was executed
+	pub fn count(&self, addr: u16) -> u64 {
+		self.counts[addr as usize]
+	}
+
+	/// Execution counts of the whole address space
+	pub fn counts(&self) -> &[u64] {
+		&self.counts
+	}
+
+	/// Number of instructions executed since the profiler was attached
+	pub fn total(&self) -> u64 {
+		self.total
+	}
+
+	/// The `n` most executed addresses, hottest first
+	pub fn hottest(&self, n: usize) -> Vec<(u16, u64)> {
+		let mut hot: Vec<(u16, u64)> = self
+			.counts
+			.iter()
+			.enumerate()
+			.filter(|&(_, &count)| count > 0)
+			.map(|(addr, &count)| (addr as u16, count))
+			.collect();
+		hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+		hot.truncate(n);
+		hot
+	}
+
+	/// Every subroutine that was entered, the one using the most instructions first
+	pub fn subroutines(&self) -> Vec<(u16, SubroutineStats)> {
+		let mut subs: Vec<(u16, SubroutineStats)> = self.subroutines.iter().map(|(&addr, &stats)| (addr, stats)).collect();
+		subs.sort_by(|a, b| b.1.instructions.cmp(&a.1.instructions).then(a.0.cmp(&b.0)));
+		subs
+	}
+
+	/// A text report of the `n` hottest addresses and subroutines, `mem` is used to disassemble them
+	pub fn report(&self, mem: &[u8], n: usize) -> String {
+		let percent = |count: u64| count as f64 * 100.0 / self.total.max(1) as f64;
+		let mut out = String::new();
+
+		let _ = writeln!(out, "{} instructions executed", self.total);
+		let _ = writeln!(out, "\nHottest addresses:");
+		for (addr, count) in self.hottest(n) {
+			let ins = disasm::decode(mem, addr);
+			let _ = writeln!(out, "{:04X} {:<20} {:>10} {:>6.2}%", addr, ins.text, count, percent(count));
+		}
+
+		let _ = writeln!(out, "\nHottest subroutines:");
+		for (addr, stats) in self.subroutines().into_iter().take(n) {
+			let _ = writeln!(
+				out,
+				"{:04X} {:>8} calls {:>10} {:>6.2}%",
+				addr,
+				stats.calls,
+				stats.instructions,
+				percent(stats.instructions)
+			);
+		}
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cpu::{cpu_with, Cpu};
+
+	// 200: CALL 206 / 202: JP 202 / 206: CALL 20C / 208: ADD V0, 1 / 20A: RET / 20C: ADD V1, 1 / 20E: RET
+	fn profiled(cycles: usize) -> Cpu {
+		let mut cpu = cpu_with(&[0x2206, 0x1202, 0x0000, 0x220C, 0x7001, 0x00EE, 0x7101, 0x00EE]);
+		cpu.set_profiler(Some(Profiler::new()));
+		for _ in 0..cycles {
+			cpu.cycle();
+		}
+		cpu
+	}
+
+	#[test]
+	fn counts_executed_addresses() {
+		let cpu = profiled(10);
+		let profiler = cpu.profiler().unwrap();
+		assert_eq!(profiler.total(), 10);
+		assert_eq!(profiler.count(0x200), 1);
+		assert_eq!(profiler.count(0x202), 4);
+		assert_eq!(profiler.count(0x204), 0);
+		assert_eq!(profiler.hottest(2), vec![(0x202, 4), (0x200, 1)]);
+	}
+
+	#[test]
+	fn groups_by_call_target() {
+		let cpu = profiled(10);
+		let subs: BTreeMap<u16, SubroutineStats> = cpu.profiler().unwrap().subroutines().into_iter().collect();
+		assert_eq!(subs[&0x200], SubroutineStats { calls: 0, instructions: 5 });
+		// CALL 20C, ADD and RET run in 206, the nested call's own instructions do not
+		assert_eq!(subs[&0x206], SubroutineStats { calls: 1, instructions: 3 });
+		assert_eq!(subs[&0x20C], SubroutineStats { calls: 1, instructions: 2 });
+	}
+
+	#[test]
+	fn report_lists_disassembly() {
+		let cpu = profiled(10);
+		let report = cpu.profiler().unwrap().report(cpu.memory(), 3);
+		assert!(report.starts_with("10 instructions executed"));
+		assert!(report.contains("0202 JP 0x202"));
+	}
+}
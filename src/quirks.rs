@@ -0,0 +1,143 @@
+// Behaviour that differs between Chip8 platforms
+//
+// A Cpu starts with the COSMAC VIP profile, frontends pick another one with `Cpu::set_quirks`.
+
+use crate::cpu::PROGRAM_START;
+use crate::disasm::instruction_len;
+
+/// The machines a rom can be written for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+	/// The original COSMAC VIP interpreter
+	#[default]
+	Vip,
+	/// SUPER-CHIP 1.1 on the HP48
+	Schip,
+	XoChip,
+}
+
+impl Platform {
+	pub const ALL: [Platform; 3] = [Platform::Vip, Platform::Schip, Platform::XoChip];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Platform::Vip => "CHIP-8",
+			Platform::Schip => "SUPER-CHIP",
+			Platform::XoChip => "XO-CHIP",
+		}
+	}
+
+	/// Instructions run per 60hz frame at the platform's usual speed
+	pub fn instructions_per_frame(self) -> usize {
+		match self {
+			Platform::Vip => 11,
+			Platform::Schip => 30,
+			Platform::XoChip => 1000,
+		}
+	}
+
+	pub fn quirks(self) -> Quirks {
+		match self {
+			Platform::Vip => Quirks { key_release: true },
+			// The HP48 interpreters finish FX0A as soon as a key goes down
+			Platform::Schip | Platform::XoChip => Quirks { key_release: false },
+		}
+	}
+
+	/// Guesses the platform a rom was written for from the instructions it can reach
+	///
+	/// Code is followed from the entry point through jumps, calls and skips so sprite data that
+	/// happens to look like an extended instruction is not counted.
+	pub fn detect(rom: &[u8]) -> Platform {
+		let word = |offset: usize| match (rom.get(offset), rom.get(offset + 1)) {
+			(Some(&hi), Some(&lo)) => Some(u16::from_be_bytes([hi, lo])),
+			_ => None,
+		};
+		let mut visited = vec![false; rom.len()];
+		let mut pending = vec![PROGRAM_START];
+		let mut platform = Platform::Vip;
+		while let Some(addr) = pending.pop() {
+			let Some(offset) = addr.checked_sub(PROGRAM_START) else {
+				continue;
+			};
+			let Some(opcode) = word(offset) else {
+				continue;
+			};
+			if visited[offset] {
+				continue;
+			}
+			visited[offset] = true;
+			platform = platform.max(Platform::of(opcode));
+
+			let next = addr + instruction_len(opcode) as usize;
+			let target = (opcode & 0xFFF) as usize;
+			match opcode & 0xF000 {
+				0x1000 => pending.push(target),
+				0x2000 => pending.extend([target, next]),
+				0x0000 if opcode == 0x00EE || opcode == 0x00FD => {}
+				// Jumps relative to V0 can't be followed
+				0xB000 => {}
+				0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000 => {
+					let skipped = word(next - PROGRAM_START).map_or(2, instruction_len) as usize;
+					pending.extend([next, next + skipped]);
+				}
+				_ => pending.push(next),
+			}
+		}
+		platform
+	}
+
+	// The oldest platform that has `opcode`
+	fn of(opcode: u16) -> Platform {
+		let x = (opcode >> 8) & 0xF;
+		match opcode & 0xF000 {
+			0x0000 if opcode & 0xFFF0 == 0x00D0 => Platform::XoChip,
+			0x0000 if opcode & 0xFFF0 == 0x00C0 || (0x00FB..=0x00FF).contains(&opcode) => Platform::Schip,
+			0x5000 if matches!(opcode & 0xF, 0x2 | 0x3) => Platform::XoChip,
+			0xD000 if opcode & 0xF == 0 => Platform::Schip,
+			0xF000 => match opcode & 0xFF {
+				0x00 if x == 0 => Platform::XoChip,
+				0x01 | 0x02 | 0x3A => Platform::XoChip,
+				0x30 | 0x75 | 0x85 => Platform::Schip,
+				_ => Platform::Vip,
+			},
+			_ => Platform::Vip,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+	/// FX0A waits for a key to be pressed and released, instead of finishing as soon as any key
+	/// is held. Keys already held when the wait starts are ignored until they are released.
+	pub key_release: bool,
+}
+
+impl Default for Quirks {
+	fn default() -> Self {
+		Platform::default().quirks()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn platforms_have_their_own_quirks() {
+		assert!(Platform::Vip.quirks().key_release);
+		assert!(!Platform::Schip.quirks().key_release);
+		assert_ne!(Platform::Vip.quirks(), Platform::XoChip.quirks());
+		assert_eq!(Quirks::default(), Platform::Vip.quirks());
+	}
+
+	#[test]
+	fn detects_reachable_extensions() {
+		// CLS / JP 0x206 / 00FF data / HIGH
+		assert_eq!(Platform::detect(&[0x00, 0xE0, 0x12, 0x06, 0x00, 0xFF, 0x00, 0xFF]), Platform::Schip);
+		// CLS / JP 0x202 / HIGH never reached
+		assert_eq!(Platform::detect(&[0x00, 0xE0, 0x12, 0x02, 0x00, 0xFF]), Platform::Vip);
+		// SE V0, 0 / LD I, long 0x300 / plane 1
+		assert_eq!(Platform::detect(&[0x30, 0x00, 0xF0, 0x00, 0x03, 0x00, 0xF1, 0x01]), Platform::XoChip);
+	}
+}
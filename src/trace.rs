@@ -0,0 +1,357 @@
+// Execution trace logging
+//
+// A tracer is attached to a Cpu with `Cpu::set_tracer` and records the machine state before
+// every instruction that passes its filter. Text traces are one line per instruction, binary
+// traces are a short header followed by fixed size records and are meant for long runs.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::disasm;
+
+const MAGIC: &[u8; 4] = b"U8TR";
+const VERSION: u8 = 1;
+const RECORD_SIZE: usize = 26;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+	Text,
+	Binary,
+}
+
+/// Broad instruction groups used to filter traces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass {
+	/// Jumps, calls, returns, skips and 00FD
+	Flow,
+	/// 6XNN, 7XNN, 8XYN and CXNN
+	Arithmetic,
+	/// Anything that reads or writes memory or I
+	Memory,
+	/// Drawing, clearing, scrolling, resolution and plane selection
+	Display,
+	/// EX9E, EXA1 and FX0A
+	Input,
+	/// Delay and sound timers and the XO-Chip audio instructions
+	Timer,
+	Unknown,
+}
+
+impl InstructionClass {
+	pub const ALL: [InstructionClass; 7] = [
+		InstructionClass::Flow,
+		InstructionClass::Arithmetic,
+		InstructionClass::Memory,
+		InstructionClass::Display,
+		InstructionClass::Input,
+		InstructionClass::Timer,
+		InstructionClass::Unknown,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			InstructionClass::Flow => "flow",
+			InstructionClass::Arithmetic => "arithmetic",
+			InstructionClass::Memory => "memory",
+			InstructionClass::Display => "display",
+			InstructionClass::Input => "input",
+			InstructionClass::Timer => "timer",
+			InstructionClass::Unknown => "unknown",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|class| class.name() == name)
+	}
+
+	pub fn of(opcode: u16) -> Self {
+		let nn = opcode & 0x00FF;
+		match opcode & 0xF000 {
+			0x0000 => match opcode {
+				0x00EE | 0x00FD => InstructionClass::Flow,
+				0x00E0 | 0x00FB | 0x00FC | 0x00FE | 0x00FF => InstructionClass::Display,
+				_ if opcode & 0xFFE0 == 0x00C0 => InstructionClass::Display,
+				_ => InstructionClass::Unknown,
+			},
+			0x1000 | 0x2000 | 0x3000 | 0x4000 | 0x9000 | 0xB000 => InstructionClass::Flow,
+			0x5000 => match opcode & 0xF {
+				0x0 => InstructionClass::Flow,
+				0x2 | 0x3 => InstructionClass::Memory,
+				_ => InstructionClass::Unknown,
+			},
+			0x6000 | 0x7000 | 0x8000 | 0xC000 => InstructionClass::Arithmetic,
+			0xA000 => InstructionClass::Memory,
+			0xD000 => InstructionClass::Display,
+			0xE000 => InstructionClass::Input,
+			_ => match nn {
+				0x01 => InstructionClass::Display,
+				0x0A => InstructionClass::Input,
+				0x02 | 0x07 | 0x15 | 0x18 | 0x3A => InstructionClass::Timer,
+				0x00 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65 | 0x75 | 0x85 => InstructionClass::Memory,
+				_ => InstructionClass::Unknown,
+			},
+		}
+	}
+}
+
+/// Which instructions get traced, an empty filter traces everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+	/// Only trace instructions at these addresses
+	pub range: Option<RangeInclusive<u16>>,
+	/// Only trace these classes of instruction
+	pub classes: Option<Vec<InstructionClass>>,
+}
+
+impl TraceFilter {
+	pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+		let in_range = self.range.as_ref().is_none_or(|range| range.contains(&pc));
+		let in_class = self.classes.as_ref().is_none_or(|classes| classes.contains(&InstructionClass::of(opcode)));
+		in_range && in_class
+	}
+}
+
+/// The machine state right before an instruction executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+	pub pc: u16,
+	pub opcode: u16,
+	/// The word after the opcode, the operand of F000 NNNN
+	pub next: u16,
+	pub v: [u8; 16],
+	pub index: u16,
+	pub dt: u8,
+	pub st: u8,
+}
+
+impl TraceEntry {
+	pub fn mnemonic(&self) -> String {
+		if self.opcode == 0xF000 {
+			format!("LD I, {:#06X}", self.next)
+		} else {
+			disasm::mnemonic(self.opcode)
+		}
+	}
+
+	/// A single trace line without the newline
+	pub fn to_text(&self) -> String {
+		let v: Vec<String> = self.v.iter().map(|r| format!("{:02X}", r)).collect();
+		format!(
+			"{:04X} {:04X} {:<20} V:{} I:{:04X} DT:{:02X} ST:{:02X}",
+			self.pc,
+			self.opcode,
+			self.mnemonic(),
+			v.join(" "),
+			self.index,
+			self.dt,
+			self.st
+		)
+	}
+
+	fn to_bytes(self) -> [u8; RECORD_SIZE] {
+		let mut out = [0; RECORD_SIZE];
+		out[0..2].copy_from_slice(&self.pc.to_be_bytes());
+		out[2..4].copy_from_slice(&self.opcode.to_be_bytes());
+		out[4..6].copy_from_slice(&self.next.to_be_bytes());
+		out[6..22].copy_from_slice(&self.v);
+		out[22..24].copy_from_slice(&self.index.to_be_bytes());
+		out[24] = self.dt;
+		out[25] = self.st;
+		out
+	}
+
+	fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Self {
+		let word = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+		let mut v = [0; 16];
+		v.copy_from_slice(&bytes[6..22]);
+		TraceEntry {
+			pc: word(0),
+			opcode: word(2),
+			next: word(4),
+			v,
+			index: word(22),
+			dt: bytes[24],
+			st: bytes[25],
+		}
+	}
+}
+
+pub struct Tracer {
+	out: Box<dyn Write + Send>,
+	format: TraceFormat,
+	filter: TraceFilter,
+	// The first write error, tracing stops once something failed
+	error: Option<io::Error>,
+	started: bool,
+}
+
+impl Tracer {
+	pub fn new(out: impl Write + Send + 'static, format: TraceFormat) -> Self {
+		Tracer {
+			out: Box::new(out),
+			format,
+			filter: TraceFilter::default(),
+			error: None,
+			started: false,
+		}
+	}
+
+	pub fn to_file(path: impl AsRef<Path>, format: TraceFormat) -> io::Result<Self> {
+		Ok(Self::new(BufWriter::new(File::create(path)?), format))
+	}
+
+	pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+		self.filter = filter;
+		self
+	}
+
+	pub fn filter(&self) -> &TraceFilter {
+		&self.filter
+	}
+
+	pub(crate) fn record(&mut self, entry: &TraceEntry) {
+		if self.error.is_some() || !self.filter.matches(entry.pc, entry.opcode) {
+			return;
+		}
+		if let Err(e) = self.write(entry) {
+			self.error = Some(e);
+		}
+	}
+
+	fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+		match self.format {
+			TraceFormat::Text => writeln!(self.out, "{}", entry.to_text()),
+			TraceFormat::Binary => {
+				if !self.started {
+					self.out.write_all(MAGIC)?;
+					self.out.write_all(&[VERSION])?;
+					self.started = true;
+				}
+				self.out.write_all(&entry.to_bytes())
+			}
+		}
+	}
+
+	/// Flushes the trace and reports the first error that happened while tracing
+	pub fn finish(mut self) -> io::Result<()> {
+		if let Some(e) = self.error.take() {
+			return Err(e);
+		}
+		self.out.flush()
+	}
+}
+
+/// Reads back a binary trace
+pub fn read_binary(mut input: impl Read) -> io::Result<Vec<TraceEntry>> {
+	let mut header = [0; 5];
+	match input.read_exact(&mut header) {
+		// Nothing was traced
+		Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(Vec::new()),
+		other => other?,
+	}
+	if &header[..4] != MAGIC || header[4] != VERSION {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "not an ultra8 binary trace"));
+	}
+
+	let mut entries = Vec::new();
+	let mut record = [0; RECORD_SIZE];
+	loop {
+		match input.read_exact(&mut record) {
+			Ok(()) => entries.push(TraceEntry::from_bytes(&record)),
+			Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(entries),
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cpu::cpu_with;
+	use std::sync::{Arc, Mutex};
+
+	// A writer the test can still read after handing it to the tracer
+	#[derive(Clone, Default)]
+	struct Shared(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	// LD V1, 0x12 / LD I, 0x300 / ADD V1, 1 / JP 0x206
+	fn run(format: TraceFormat, filter: TraceFilter, cycles: usize) -> Vec<u8> {
+		let out = Shared::default();
+		let mut cpu = cpu_with(&[0x6112, 0xA300, 0x7101, 0x1206]);
+		cpu.set_tracer(Some(Tracer::new(out.clone(), format).with_filter(filter)));
+		for _ in 0..cycles {
+			cpu.cycle();
+		}
+		cpu.set_tracer(None).unwrap().finish().unwrap();
+		let bytes = out.0.lock().unwrap().clone();
+		bytes
+	}
+
+	#[test]
+	fn text_trace_has_one_line_per_instruction() {
+		let out = String::from_utf8(run(TraceFormat::Text, TraceFilter::default(), 3)).unwrap();
+		let lines: Vec<&str> = out.lines().collect();
+		assert_eq!(lines.len(), 3);
+		assert!(lines[0].starts_with("0200 6112 LD V1, 0x12"));
+		// State is recorded before the instruction runs
+		assert!(lines[1].contains("V:00 12 00"));
+		assert!(lines[2].contains("I:0300"));
+	}
+
+	#[test]
+	fn filters_by_range_and_class() {
+		let filter = TraceFilter { range: Some(0x202..=0x206), classes: None };
+		let out = String::from_utf8(run(TraceFormat::Text, filter, 4)).unwrap();
+		assert_eq!(out.lines().count(), 3);
+
+		let filter = TraceFilter { range: None, classes: Some(vec![InstructionClass::Arithmetic]) };
+		let out = String::from_utf8(run(TraceFormat::Text, filter, 6)).unwrap();
+		assert_eq!(out.lines().count(), 2);
+		assert!(out.lines().all(|l| l.contains(" LD V1") || l.contains(" ADD V1")));
+	}
+
+	#[test]
+	fn binary_trace_round_trips() {
+		let bytes = run(TraceFormat::Binary, TraceFilter::default(), 4);
+		assert_eq!(bytes.len(), 5 + 4 * RECORD_SIZE);
+
+		let entries = read_binary(&bytes[..]).unwrap();
+		assert_eq!(entries.len(), 4);
+		assert_eq!(entries[1].pc, 0x202);
+		assert_eq!(entries[1].opcode, 0xA300);
+		assert_eq!(entries[2].index, 0x300);
+		assert_eq!(entries[3].v[1], 0x13);
+	}
+
+	#[test]
+	fn read_binary_rejects_other_files() {
+		assert!(read_binary(&b"hello world"[..]).is_err());
+		assert!(read_binary(&b""[..]).unwrap().is_empty());
+	}
+
+	#[test]
+	fn classes() {
+		assert_eq!(InstructionClass::of(0x00EE), InstructionClass::Flow);
+		assert_eq!(InstructionClass::of(0x00C3), InstructionClass::Display);
+		assert_eq!(InstructionClass::of(0x00D3), InstructionClass::Display);
+		assert_eq!(InstructionClass::of(0x8124), InstructionClass::Arithmetic);
+		assert_eq!(InstructionClass::of(0xF155), InstructionClass::Memory);
+		assert_eq!(InstructionClass::of(0xF000), InstructionClass::Memory);
+		assert_eq!(InstructionClass::of(0xF10A), InstructionClass::Input);
+		assert_eq!(InstructionClass::of(0xF115), InstructionClass::Timer);
+		assert_eq!(InstructionClass::from_name("display"), Some(InstructionClass::Display));
+	}
+}